@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN role VARCHAR NOT NULL DEFAULT 'reader'
+  CHECK (role IN ('admin', 'reader'));
@@ -0,0 +1,4 @@
+UPDATE users SET role = 'reader' WHERE role IN ('editor', 'author');
+ALTER TABLE users DROP CONSTRAINT users_role_check;
+ALTER TABLE users ADD CONSTRAINT users_role_check
+  CHECK (role IN ('admin', 'reader'));
@@ -0,0 +1,2 @@
+DROP INDEX users_email_unique;
+DROP INDEX users_username_unique;
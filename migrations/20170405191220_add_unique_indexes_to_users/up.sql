@@ -0,0 +1,2 @@
+CREATE UNIQUE INDEX users_username_unique ON users (lower(username));
+CREATE UNIQUE INDEX users_email_unique ON users (lower(email));
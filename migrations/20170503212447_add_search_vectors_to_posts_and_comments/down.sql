@@ -0,0 +1,8 @@
+DROP INDEX comments_search_vector_index;
+DROP TRIGGER comments_search_vector_update ON comments;
+DROP FUNCTION comments_search_vector_update();
+ALTER TABLE comments DROP COLUMN search_vector;
+DROP INDEX posts_search_vector_index;
+DROP TRIGGER posts_search_vector_update ON posts;
+DROP FUNCTION posts_search_vector_update();
+ALTER TABLE posts DROP COLUMN search_vector;
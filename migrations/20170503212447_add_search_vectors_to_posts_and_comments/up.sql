@@ -0,0 +1,39 @@
+-- Kept up to date by triggers rather than generated columns, which need
+-- Postgres 12.
+ALTER TABLE posts ADD COLUMN search_vector tsvector;
+
+CREATE FUNCTION posts_search_vector_update() RETURNS trigger AS $$
+BEGIN
+  NEW.search_vector :=
+    setweight(to_tsvector('english', coalesce(NEW.title, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(NEW.body, '')), 'B');
+  RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER posts_search_vector_update
+  BEFORE INSERT OR UPDATE OF title, body ON posts
+  FOR EACH ROW EXECUTE PROCEDURE posts_search_vector_update();
+
+UPDATE posts SET search_vector =
+  setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+  setweight(to_tsvector('english', coalesce(body, '')), 'B');
+ALTER TABLE posts ALTER COLUMN search_vector SET NOT NULL;
+CREATE INDEX posts_search_vector_index ON posts USING GIN (search_vector);
+
+ALTER TABLE comments ADD COLUMN search_vector tsvector;
+
+CREATE FUNCTION comments_search_vector_update() RETURNS trigger AS $$
+BEGIN
+  NEW.search_vector := to_tsvector('english', coalesce(NEW.body, ''));
+  RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER comments_search_vector_update
+  BEFORE INSERT OR UPDATE OF body ON comments
+  FOR EACH ROW EXECUTE PROCEDURE comments_search_vector_update();
+
+UPDATE comments SET search_vector = to_tsvector('english', coalesce(body, ''));
+ALTER TABLE comments ALTER COLUMN search_vector SET NOT NULL;
+CREATE INDEX comments_search_vector_index ON comments USING GIN (search_vector);
@@ -0,0 +1,2 @@
+ALTER TABLE posts ADD COLUMN publish_at TIMESTAMP;
+CREATE INDEX posts_publish_at_index ON posts (publish_at) WHERE NOT published;
@@ -0,0 +1,3 @@
+DROP TABLE post_slugs;
+DROP INDEX posts_slug_unique;
+ALTER TABLE posts DROP COLUMN slug;
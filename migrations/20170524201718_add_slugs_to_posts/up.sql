@@ -0,0 +1,13 @@
+ALTER TABLE posts ADD COLUMN slug VARCHAR;
+UPDATE posts
+  SET slug = trim(BOTH '-' FROM regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g')) || '-' || id;
+ALTER TABLE posts ALTER COLUMN slug SET NOT NULL;
+CREATE UNIQUE INDEX posts_slug_unique ON posts (slug);
+
+CREATE TABLE post_slugs (
+  id SERIAL PRIMARY KEY,
+  post_id INTEGER NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+  slug VARCHAR NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+CREATE UNIQUE INDEX post_slugs_slug_unique ON post_slugs (slug);
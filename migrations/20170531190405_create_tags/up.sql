@@ -0,0 +1,14 @@
+CREATE TABLE tags (
+  id SERIAL PRIMARY KEY,
+  name VARCHAR NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+CREATE UNIQUE INDEX tags_name_unique ON tags (name);
+
+CREATE TABLE post_tags (
+  id SERIAL PRIMARY KEY,
+  post_id INTEGER NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+  tag_id INTEGER NOT NULL REFERENCES tags (id) ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX post_tags_tag_unique ON post_tags (post_id, tag_id);
+CREATE INDEX post_tags_tag_id_index ON post_tags (tag_id);
@@ -0,0 +1,2 @@
+DROP INDEX comments_parent_id_index;
+ALTER TABLE comments DROP COLUMN parent_id;
@@ -0,0 +1,2 @@
+DROP INDEX comments_status_index;
+ALTER TABLE comments DROP COLUMN status;
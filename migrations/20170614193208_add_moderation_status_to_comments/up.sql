@@ -0,0 +1,4 @@
+ALTER TABLE comments ADD COLUMN status VARCHAR NOT NULL DEFAULT 'pending'
+  CHECK (status IN ('pending', 'approved', 'rejected', 'spam'));
+UPDATE comments SET status = 'approved' WHERE published;
+CREATE INDEX comments_status_index ON comments (status);
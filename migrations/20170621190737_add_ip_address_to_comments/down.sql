@@ -0,0 +1,2 @@
+DROP INDEX comments_ip_address_created_at_index;
+ALTER TABLE comments DROP COLUMN ip_address;
@@ -0,0 +1,2 @@
+ALTER TABLE comments ADD COLUMN ip_address VARCHAR;
+CREATE INDEX comments_ip_address_created_at_index ON comments (ip_address, created_at);
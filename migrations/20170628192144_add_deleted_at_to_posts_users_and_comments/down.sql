@@ -0,0 +1,7 @@
+DROP INDEX comments_deleted_at_index;
+DROP INDEX users_deleted_at_index;
+DROP INDEX posts_deleted_at_index;
+
+ALTER TABLE comments DROP COLUMN deleted_at;
+ALTER TABLE users DROP COLUMN deleted_at;
+ALTER TABLE posts DROP COLUMN deleted_at;
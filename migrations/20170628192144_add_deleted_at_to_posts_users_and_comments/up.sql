@@ -0,0 +1,7 @@
+ALTER TABLE posts ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE comments ADD COLUMN deleted_at TIMESTAMP;
+
+CREATE INDEX posts_deleted_at_index ON posts (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX users_deleted_at_index ON users (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX comments_deleted_at_index ON comments (deleted_at) WHERE deleted_at IS NOT NULL;
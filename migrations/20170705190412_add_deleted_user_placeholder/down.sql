@@ -0,0 +1,3 @@
+DELETE FROM users WHERE placeholder;
+DROP INDEX users_placeholder_unique;
+ALTER TABLE users DROP COLUMN placeholder;
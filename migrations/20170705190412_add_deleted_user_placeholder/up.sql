@@ -0,0 +1,8 @@
+ALTER TABLE users ADD COLUMN placeholder BOOLEAN NOT NULL DEFAULT 'f';
+CREATE UNIQUE INDEX users_placeholder_unique ON users (placeholder) WHERE placeholder;
+-- Account that content of deleted users can be reassigned to, found by its
+-- flag rather than by its name. Brackets are not allowed in usernames, so no
+-- existing account can clash with it, and the empty password hash never
+-- verifies, so nobody can log in as it.
+INSERT INTO users (username, name, email, password_hash, placeholder)
+  VALUES ('[deleted]', 'Deleted user', 'deleted@localhost', '', 't');
@@ -0,0 +1,4 @@
+-- Rendered from the Markdown in `body`. Left NULL here, existing rows get
+-- rendered by the server when it starts.
+ALTER TABLE posts ADD COLUMN body_html VARCHAR;
+ALTER TABLE comments ADD COLUMN body_html VARCHAR;
@@ -0,0 +1,5 @@
+-- Purging a comment from the trash must not take its live replies with it,
+-- they are kept as top-level comments instead.
+ALTER TABLE comments DROP CONSTRAINT comments_parent_id_fkey;
+ALTER TABLE comments ADD CONSTRAINT comments_parent_id_fkey
+  FOREIGN KEY (parent_id) REFERENCES comments (id) ON DELETE SET NULL;
@@ -0,0 +1,318 @@
+use std::fmt;
+use std::error;
+use std::io;
+use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64;
+use crypto::hmac::Hmac;
+use crypto::mac::{Mac, MacResult};
+use crypto::scrypt::{self, ScryptParams};
+use crypto::sha2::Sha256;
+use diesel::prelude::*;
+
+use rocket::Outcome;
+use rocket::State;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+
+use config::{AuthConfig, Config};
+use db::{Db, DbError};
+use models::User;
+use schema::users::dsl::*;
+
+const BEARER_PREFIX: &'static str = "Bearer ";
+
+// scrypt work factors: N = 2^14, r = 8, p = 1.
+const SCRYPT_LOG_N: u8 = 14;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+#[derive(Debug)]
+pub enum AuthError {
+    MissingToken,
+    MalformedToken,
+    InvalidSignature,
+    ExpiredToken,
+    InvalidCredentials,
+    PasswordHashing(io::Error),
+    MissingState(&'static str),
+    Db(DbError),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AuthError::MissingToken => write!(f, "No bearer token was provided"),
+            AuthError::MalformedToken => write!(f, "The bearer token is malformed"),
+            AuthError::InvalidSignature => write!(f, "The bearer token signature is invalid"),
+            AuthError::ExpiredToken => write!(f, "The bearer token has expired"),
+            AuthError::InvalidCredentials => write!(f, "Invalid username or password"),
+            AuthError::PasswordHashing(_) => write!(f, "Error hashing the password"),
+            AuthError::MissingState(state) => write!(f, "{} is not managed by Rocket", state),
+            AuthError::Db(_) => write!(f, "Db error while authenticating"),
+        }
+    }
+}
+
+impl error::Error for AuthError {
+    fn description(&self) -> &str {
+        match *self {
+            AuthError::MissingToken => "missing token",
+            AuthError::MalformedToken => "malformed token",
+            AuthError::InvalidSignature => "invalid token signature",
+            AuthError::ExpiredToken => "expired token",
+            AuthError::InvalidCredentials => "invalid credentials",
+            AuthError::PasswordHashing(ref err) => err.description(),
+            AuthError::MissingState(_) => "missing managed state",
+            AuthError::Db(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            AuthError::PasswordHashing(ref err) => Some(err),
+            AuthError::Db(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DbError> for AuthError {
+    fn from(err: DbError) -> AuthError {
+        AuthError::Db(err)
+    }
+}
+
+/// A signed, expiring bearer token identifying a user.
+///
+/// The wire format is `base64(user_id.expires_at).base64(hmac_sha256(payload))`.
+#[derive(Debug, PartialEq)]
+pub struct Token {
+    pub user_id: i32,
+    pub expires_at: u64,
+}
+
+impl Token {
+    pub fn new(for_user_id: i32, ttl: u64) -> Token {
+        Token {
+            user_id: for_user_id,
+            expires_at: now() + ttl,
+        }
+    }
+
+    pub fn encode(&self, secret: &str) -> String {
+        let payload = format!("{}.{}", self.user_id, self.expires_at);
+        let signature = sign(payload.as_bytes(), secret);
+
+        format!("{}.{}",
+                base64::encode_config(payload.as_bytes(), base64::URL_SAFE),
+                base64::encode_config(signature.code(), base64::URL_SAFE))
+    }
+
+    pub fn decode(token: &str, secret: &str) -> Result<Token, AuthError> {
+        let mut parts = token.splitn(2, '.');
+        let (payload, signature) = match (parts.next(), parts.next()) {
+            (Some(payload), Some(signature)) => (payload, signature),
+            _ => return Err(AuthError::MalformedToken),
+        };
+
+        let payload = base64::decode_config(payload, base64::URL_SAFE)
+            .map_err(|_| AuthError::MalformedToken)?;
+        let signature = base64::decode_config(signature, base64::URL_SAFE)
+            .map_err(|_| AuthError::MalformedToken)?;
+
+        if sign(&payload, secret) != MacResult::new(&signature) {
+            return Err(AuthError::InvalidSignature);
+        }
+
+        let payload = str::from_utf8(&payload).map_err(|_| AuthError::MalformedToken)?;
+        let mut fields = payload.splitn(2, '.');
+        let token = match (fields.next().map(str::parse), fields.next().map(str::parse)) {
+            (Some(Ok(for_user_id)), Some(Ok(expires_at))) => {
+                Token {
+                    user_id: for_user_id,
+                    expires_at: expires_at,
+                }
+            }
+            _ => return Err(AuthError::MalformedToken),
+        };
+
+        if token.is_expired() {
+            return Err(AuthError::ExpiredToken);
+        }
+
+        Ok(token)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= now()
+    }
+}
+
+fn sign(payload: &[u8], secret: &str) -> MacResult {
+    let mut hmac = Hmac::new(Sha256::new(), secret.as_bytes());
+    hmac.input(payload);
+    hmac.result()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is before the epoch")
+        .as_secs()
+}
+
+/// Issues a token for `user` using the configured secret and TTL.
+pub fn issue_token(user: &User, config: &AuthConfig) -> Token {
+    Token::new(user.id, config.token_ttl)
+}
+
+/// Hashes `password` with scrypt and a random salt.
+///
+/// The result is self-describing (it embeds the salt and work factors) and is
+/// what gets stored in `users.password_hash`.
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let params = ScryptParams::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P);
+
+    scrypt::scrypt_simple(password, &params).map_err(AuthError::PasswordHashing)
+}
+
+/// Checks `password` against the hash stored for `user`.
+///
+/// Users created before passwords existed have an empty hash and never match.
+pub fn verify_password(user: &User, password: &str) -> bool {
+    scrypt::scrypt_check(password, &user.password_hash).unwrap_or(false)
+}
+
+/// Spends the same scrypt work as `verify_password` without a user to check
+/// against, so logging in as an unknown username takes as long as logging in
+/// with a wrong password. Always fails.
+pub fn verify_dummy_password(password: &str) -> bool {
+    let _ = hash_password(password);
+    false
+}
+
+/// Request guard resolving the `Authorization: Bearer <token>` header to a `User`.
+///
+/// Fails with `401 Unauthorized` when the token is missing, invalid, expired or
+/// refers to a user that no longer exists or was deleted.
+pub struct CurrentUser(pub User);
+
+impl<'a, 'r> FromRequest<'a, 'r> for CurrentUser {
+    type Error = AuthError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<CurrentUser, AuthError> {
+        let header = match request.headers().get_one("Authorization") {
+            Some(header) if header.starts_with(BEARER_PREFIX) => &header[BEARER_PREFIX.len()..],
+            Some(_) => return Outcome::Failure((Status::Unauthorized, AuthError::MalformedToken)),
+            None => return Outcome::Failure((Status::Unauthorized, AuthError::MissingToken)),
+        };
+
+        let config = match State::<Config>::from_request(request) {
+            Outcome::Success(config) => config,
+            _ => return missing_state("Config"),
+        };
+
+        let db = match State::<Db>::from_request(request) {
+            Outcome::Success(db) => db,
+            _ => return missing_state("Db"),
+        };
+
+        let token = match Token::decode(header, &config.auth().secret) {
+            Ok(token) => token,
+            Err(err) => return Outcome::Failure((Status::Unauthorized, err)),
+        };
+
+        match find_user(&db, token.user_id) {
+            Ok(user) => Outcome::Success(CurrentUser(user)),
+            Err(err) => Outcome::Failure((Status::Unauthorized, err)),
+        }
+    }
+}
+
+fn missing_state(state: &'static str) -> request::Outcome<CurrentUser, AuthError> {
+    Outcome::Failure((Status::InternalServerError, AuthError::MissingState(state)))
+}
+
+fn find_user(db: &Db, user_id: i32) -> Result<User, AuthError> {
+    let conn = &*db.pool().get().map_err(DbError::from)?;
+
+    users.find(user_id)
+        .filter(deleted_at.is_null())
+        .first::<User>(conn)
+        .map_err(|err| AuthError::Db(DbError::from(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &'static str = "test secret";
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let token = Token::new(42, 60);
+
+        assert_eq!(Token::decode(&token.encode(SECRET), SECRET).unwrap(), token);
+    }
+
+    #[test]
+    fn rejects_a_tampered_payload() {
+        let encoded = Token::new(42, 60).encode(SECRET);
+        let signature = encoded.splitn(2, '.').nth(1).unwrap();
+        let forged_payload = format!("{}.{}", 1, now() + 60);
+        let forged = format!("{}.{}",
+                             base64::encode_config(forged_payload.as_bytes(), base64::URL_SAFE),
+                             signature);
+
+        match Token::decode(&forged, SECRET) {
+            Err(AuthError::InvalidSignature) => {}
+            other => panic!("expected an invalid signature, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_a_token_signed_with_another_secret() {
+        let encoded = Token::new(42, 60).encode("another secret");
+
+        match Token::decode(&encoded, SECRET) {
+            Err(AuthError::InvalidSignature) => {}
+            other => panic!("expected an invalid signature, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_an_expired_token() {
+        let token = Token {
+            user_id: 42,
+            expires_at: now() - 1,
+        };
+
+        match Token::decode(&token.encode(SECRET), SECRET) {
+            Err(AuthError::ExpiredToken) => {}
+            other => panic!("expected an expired token, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_tokens() {
+        let signed = |payload: &str| {
+            format!("{}.{}",
+                    base64::encode_config(payload.as_bytes(), base64::URL_SAFE),
+                    base64::encode_config(sign(payload.as_bytes(), SECRET).code(),
+                                          base64::URL_SAFE))
+        };
+
+        for token in &[String::new(),
+                       String::from("no-dot"),
+                       String::from("not base64!.not base64!"),
+                       signed("42"),
+                       signed("forty-two.later")] {
+            match Token::decode(token, SECRET) {
+                Err(AuthError::MalformedToken) => {}
+                other => panic!("expected {:?} to be malformed, got {:?}", token, other),
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use toml::Parser;
+use toml::{Parser, Table};
 
 use std::io::prelude::*;
 use std::io;
@@ -6,46 +6,102 @@ use std::fs::File;
 use std::fmt;
 use std::error;
 use std::path::Path;
+use std::env as std_env;
 
+use deletion::DeletePolicy;
 use env::Env;
+use sanitization::SanitizationPolicy;
 
 const CONFIG_DIR: &'static str = "./config";
 const DB_CONFIG_FILE: &'static str = "database.toml";
+const AUTH_CONFIG_FILE: &'static str = "auth.toml";
+const SPAM_CONFIG_FILE: &'static str = "spam.toml";
+const TRASH_CONFIG_FILE: &'static str = "trash.toml";
+const DELETION_CONFIG_FILE: &'static str = "deletion.toml";
+const SANITIZATION_CONFIG_FILE: &'static str = "sanitization.toml";
+
+const AUTH_SECRET_VAR: &'static str = "BLOG_AUTH_SECRET";
+const PLACEHOLDER_SECRET_MARKER: &'static str = "change-me";
+
+const DEFAULT_TOKEN_TTL: u64 = 86400;
+const DEFAULT_SPAM_THRESHOLD: f64 = 0.9;
+const DEFAULT_REVIEW_THRESHOLD: f64 = 0.5;
+const DEFAULT_MAX_LINKS: u32 = 2;
+const DEFAULT_REPEAT_WINDOW: u64 = 3600;
+const DEFAULT_VELOCITY_WINDOW: u64 = 60;
+const DEFAULT_MAX_COMMENTS_PER_WINDOW: u32 = 5;
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+const DEFAULT_PURGE_INTERVAL: u64 = 3600;
 
 #[derive(Debug)]
-pub enum DbConfigError {
+pub enum ConfigFileError {
     Io(io::Error),
     Parsing(String),
 }
 
-impl fmt::Display for DbConfigError {
+impl fmt::Display for ConfigFileError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            DbConfigError::Io(_) => write!(f, "Error accessing the DB config file"),
-            DbConfigError::Parsing(_) => write!(f, "Error parsing DB config file"),
+            ConfigFileError::Io(_) => write!(f, "Error accessing the config file"),
+            ConfigFileError::Parsing(_) => write!(f, "Error parsing the config file"),
         }
     }
 }
 
-impl error::Error for DbConfigError {
+impl error::Error for ConfigFileError {
     fn description(&self) -> &str {
         match *self {
-            DbConfigError::Io(ref err) => err.description(),
-            DbConfigError::Parsing(ref err) => err,
+            ConfigFileError::Io(ref err) => err.description(),
+            ConfigFileError::Parsing(ref err) => err,
         }
     }
 
     fn cause(&self) -> Option<&error::Error> {
         match *self {
-            DbConfigError::Io(ref err) => Some(err),
-            DbConfigError::Parsing(_) => Some(self),
+            ConfigFileError::Io(ref err) => Some(err),
+            ConfigFileError::Parsing(_) => Some(self),
         }
     }
 }
 
-impl From<io::Error> for DbConfigError {
-    fn from(err: io::Error) -> DbConfigError {
-        DbConfigError::Io(err)
+impl From<io::Error> for ConfigFileError {
+    fn from(err: io::Error) -> ConfigFileError {
+        ConfigFileError::Io(err)
+    }
+}
+
+fn load_env_table(file_name: &str, env: &Env) -> Result<Table, ConfigFileError> {
+    let config_file_path = Path::new(CONFIG_DIR).join(file_name);
+    let mut config_file = File::open(config_file_path)?;
+    let mut buffer = String::new();
+    config_file.read_to_string(&mut buffer)?;
+
+    let mut parser = Parser::new(&buffer);
+    let toml = match parser.parse() {
+        None => {
+            let desc = parser.errors
+                .iter()
+                .fold(String::new(), |acc, error| acc + &format!("{}", error));
+            return Err(ConfigFileError::Parsing(format!("Parsing error {}", desc)));
+        }
+        Some(toml) => toml,
+    };
+
+    match toml.get(&env.to_string()) {
+        None => {
+            Err(ConfigFileError::Parsing(format!("no configuration found for env '{}'",
+                                                 env.to_string())))
+        }
+        Some(toml) => {
+            match toml.as_table() {
+                None => {
+                    Err(ConfigFileError::Parsing(format!("configuration section for env '{}' \
+                                                          does not have the correct format",
+                                                         env.to_string())))
+                }
+                Some(toml) => Ok(toml.clone()),
+            }
+        }
     }
 }
 
@@ -80,40 +136,8 @@ impl DbConfig {
         }
     }
 
-    pub fn load(env: &Env) -> Result<DbConfig, DbConfigError> {
-        let config_file_path = Path::new(CONFIG_DIR).join(DB_CONFIG_FILE);
-        let mut config_file = File::open(config_file_path)?;
-        let mut buffer = String::new();
-        config_file.read_to_string(&mut buffer)?;
-
-        let mut parser = Parser::new(&buffer);
-        let toml = match parser.parse() {
-            None => {
-                let desc = parser.errors
-                    .iter()
-                    .fold(String::new(), |acc, error| acc + &format!("{}", error));
-                return Err(DbConfigError::Parsing(format!("Parsing error {}", desc)));
-            }
-            Some(toml) => toml,
-        };
-
-        let env_toml = match toml.get(&env.to_string()) {
-            None => {
-                return Err(DbConfigError::Parsing(format!("no configuration found for env '{}'",
-                                                          env.to_string())))
-            }
-            Some(toml) => {
-                match toml.as_table() {
-                    None => {
-                        return Err(DbConfigError::Parsing(format!("configuration section for \
-                                                                   env '{}' does not have the \
-                                                                   correct format",
-                                                                  env.to_string())))
-                    }
-                    Some(toml) => toml,
-                }
-            }
-        };
+    pub fn load(env: &Env) -> Result<DbConfig, ConfigFileError> {
+        let env_toml = load_env_table(DB_CONFIG_FILE, env)?;
 
         let adapter = match env_toml.get("adapter") {
             None => "postgres",
@@ -127,21 +151,21 @@ impl DbConfig {
 
         let database = match env_toml.get("database") {
             None => {
-                return Err(DbConfigError::Parsing(String::from("'database' key does not exist.")))
+                return Err(ConfigFileError::Parsing(String::from("'database' key does not exist.")))
             }
             Some(database) => database.as_str().expect("invalid database: must me a string"),
         };
 
         let username = match env_toml.get("username") {
             None => {
-                return Err(DbConfigError::Parsing(String::from("'username' key does not exist.")))
+                return Err(ConfigFileError::Parsing(String::from("'username' key does not exist.")))
             }
             Some(username) => username.as_str().expect("invalid username: must me a string"),
         };
 
         let password = match env_toml.get("password") {
             None => {
-                return Err(DbConfigError::Parsing(String::from("'password' key does not exist.")))
+                return Err(ConfigFileError::Parsing(String::from("'password' key does not exist.")))
             }
             Some(password) => password.as_str().expect("invalid password: must me a string"),
         };
@@ -176,35 +200,402 @@ impl DbConfig {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct AuthConfig {
+    pub secret: String,
+    pub token_ttl: u64,
+}
+
+impl AuthConfig {
+    pub fn new(secret: &str, token_ttl: u64) -> AuthConfig {
+        AuthConfig {
+            secret: secret.to_owned(),
+            token_ttl: token_ttl,
+        }
+    }
+
+    /// `BLOG_AUTH_SECRET` takes precedence over the `secret` key. Outside of
+    /// development and test the secret must come from one of them and must not
+    /// be a placeholder, or the server refuses to start.
+    pub fn load(env: &Env) -> Result<AuthConfig, ConfigFileError> {
+        let env_toml = load_env_table(AUTH_CONFIG_FILE, env)?;
+
+        let secret = match std_env::var(AUTH_SECRET_VAR) {
+            Ok(secret) => Some(secret),
+            Err(_) => {
+                env_toml.get("secret")
+                    .map(|secret| secret.as_str().expect("invalid secret: must be a string"))
+                    .map(str::to_owned)
+            }
+        };
+        let secret = check_secret(env, secret)?;
+
+        let token_ttl = match env_toml.get("token_ttl") {
+            None => DEFAULT_TOKEN_TTL,
+            Some(ttl) => ttl.as_integer().expect("invalid token_ttl: must be an integer") as u64,
+        };
+
+        Ok(Self::new(&secret, token_ttl))
+    }
+}
+
+fn check_secret(env: &Env, secret: Option<String>) -> Result<String, ConfigFileError> {
+    let secret = match secret {
+        None => {
+            return Err(ConfigFileError::Parsing(format!("no secret: set {} or the 'secret' key.",
+                                                        AUTH_SECRET_VAR)))
+        }
+        Some(secret) => secret,
+    };
+
+    match *env {
+        Env::Development | Env::Test => Ok(secret),
+        Env::Staging | Env::Production => {
+            if secret.trim().is_empty() || secret.contains(PLACEHOLDER_SECRET_MARKER) {
+                let message = format!("placeholder secret: set {}.", AUTH_SECRET_VAR);
+                Err(ConfigFileError::Parsing(message))
+            } else {
+                Ok(secret)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SpamConfig {
+    pub spam_threshold: f64,
+    pub review_threshold: f64,
+    pub max_links: u32,
+    pub banned_words: Vec<String>,
+    pub repeat_window: u64,
+    pub velocity_window: u64,
+    pub max_comments_per_window: u32,
+}
+
+impl Default for SpamConfig {
+    fn default() -> SpamConfig {
+        SpamConfig {
+            spam_threshold: DEFAULT_SPAM_THRESHOLD,
+            review_threshold: DEFAULT_REVIEW_THRESHOLD,
+            max_links: DEFAULT_MAX_LINKS,
+            banned_words: Vec::new(),
+            repeat_window: DEFAULT_REPEAT_WINDOW,
+            velocity_window: DEFAULT_VELOCITY_WINDOW,
+            max_comments_per_window: DEFAULT_MAX_COMMENTS_PER_WINDOW,
+        }
+    }
+}
+
+impl SpamConfig {
+    /// Every key is optional and falls back to its default.
+    pub fn load(env: &Env) -> Result<SpamConfig, ConfigFileError> {
+        let env_toml = load_env_table(SPAM_CONFIG_FILE, env)?;
+        let mut config = SpamConfig::default();
+
+        if let Some(threshold) = env_toml.get("spam_threshold") {
+            config.spam_threshold = threshold.as_float()
+                .expect("invalid spam_threshold: must be a float");
+        }
+
+        if let Some(threshold) = env_toml.get("review_threshold") {
+            config.review_threshold = threshold.as_float()
+                .expect("invalid review_threshold: must be a float");
+        }
+
+        if let Some(links) = env_toml.get("max_links") {
+            config.max_links = links.as_integer()
+                .expect("invalid max_links: must be an integer") as u32;
+        }
+
+        if let Some(words) = env_toml.get("banned_words") {
+            config.banned_words = words.as_slice()
+                .expect("invalid banned_words: must be an array")
+                .iter()
+                .map(|word| {
+                    word.as_str().expect("invalid banned word: must be a string").to_lowercase()
+                })
+                .collect();
+        }
+
+        if let Some(window) = env_toml.get("repeat_window") {
+            config.repeat_window = window.as_integer()
+                .expect("invalid repeat_window: must be an integer") as u64;
+        }
+
+        if let Some(window) = env_toml.get("velocity_window") {
+            config.velocity_window = window.as_integer()
+                .expect("invalid velocity_window: must be an integer") as u64;
+        }
+
+        if let Some(max) = env_toml.get("max_comments_per_window") {
+            config.max_comments_per_window = max.as_integer()
+                .expect("invalid max_comments_per_window: must be an integer") as u32;
+        }
+
+        Ok(config)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrashConfig {
+    pub retention_days: i64,
+    pub purge_interval: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> TrashConfig {
+        TrashConfig {
+            retention_days: DEFAULT_RETENTION_DAYS,
+            purge_interval: DEFAULT_PURGE_INTERVAL,
+        }
+    }
+}
+
+impl TrashConfig {
+    /// Every key is optional and falls back to its default.
+    pub fn load(env: &Env) -> Result<TrashConfig, ConfigFileError> {
+        let env_toml = load_env_table(TRASH_CONFIG_FILE, env)?;
+        let mut config = TrashConfig::default();
+
+        if let Some(days) = env_toml.get("retention_days") {
+            config.retention_days = days.as_integer()
+                .expect("invalid retention_days: must be an integer");
+        }
+
+        if let Some(interval) = env_toml.get("purge_interval") {
+            config.purge_interval = interval.as_integer()
+                .expect("invalid purge_interval: must be an integer") as u64;
+        }
+
+        Ok(config)
+    }
+}
+
+/// What deleting a user or a post does to the records depending on it, one
+/// policy per relationship.
+#[derive(Clone, Debug)]
+pub struct DeletionConfig {
+    pub user_posts: DeletePolicy,
+    pub user_comments: DeletePolicy,
+    pub post_comments: DeletePolicy,
+}
+
+impl Default for DeletionConfig {
+    fn default() -> DeletionConfig {
+        DeletionConfig {
+            user_posts: DeletePolicy::Reassign,
+            user_comments: DeletePolicy::Reassign,
+            post_comments: DeletePolicy::Cascade,
+        }
+    }
+}
+
+impl DeletionConfig {
+    /// Every key is optional and falls back to its default. Comments cannot be
+    /// reassigned to another post, so `post_comments` only takes `cascade` or
+    /// `restrict`.
+    pub fn load(env: &Env) -> Result<DeletionConfig, ConfigFileError> {
+        let env_toml = load_env_table(DELETION_CONFIG_FILE, env)?;
+        let mut config = DeletionConfig::default();
+
+        if let Some(policy) = env_toml.get("user_posts") {
+            config.user_posts = parse_policy("user_posts", policy.as_str())?;
+        }
+
+        if let Some(policy) = env_toml.get("user_comments") {
+            config.user_comments = parse_policy("user_comments", policy.as_str())?;
+        }
+
+        if let Some(policy) = env_toml.get("post_comments") {
+            config.post_comments = parse_policy("post_comments", policy.as_str())?;
+            if config.post_comments == DeletePolicy::Reassign {
+                return Err(ConfigFileError::Parsing(String::from("'post_comments' cannot be \
+                                                                  'reassign'")));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_policy(key: &str, value: Option<&str>) -> Result<DeletePolicy, ConfigFileError> {
+    value.and_then(|value| value.parse().ok())
+        .ok_or_else(|| {
+            ConfigFileError::Parsing(format!("invalid {}: must be one of 'cascade', \
+                                              'reassign' or 'restrict'",
+                                             key))
+        })
+}
+
+/// The sanitization policy of each user-supplied field. Titles, names and tags
+/// are cleaned as they are received, Markdown bodies when they are rendered.
+#[derive(Clone, Debug)]
+pub struct SanitizationConfig {
+    pub post_title: SanitizationPolicy,
+    pub post_body: SanitizationPolicy,
+    pub comment_body: SanitizationPolicy,
+    pub user_name: SanitizationPolicy,
+    pub tag_name: SanitizationPolicy,
+}
+
+impl Default for SanitizationConfig {
+    fn default() -> SanitizationConfig {
+        SanitizationConfig {
+            post_title: SanitizationPolicy::PlainText,
+            post_body: SanitizationPolicy::LimitedHtml,
+            comment_body: SanitizationPolicy::LimitedHtml,
+            user_name: SanitizationPolicy::PlainText,
+            tag_name: SanitizationPolicy::PlainText,
+        }
+    }
+}
+
+impl SanitizationConfig {
+    /// Every key is optional and falls back to its default.
+    pub fn load(env: &Env) -> Result<SanitizationConfig, ConfigFileError> {
+        let env_toml = load_env_table(SANITIZATION_CONFIG_FILE, env)?;
+        let mut config = SanitizationConfig::default();
+
+        if let Some(policy) = env_toml.get("post_title") {
+            config.post_title = parse_sanitization_policy("post_title", policy.as_str())?;
+        }
+
+        if let Some(policy) = env_toml.get("post_body") {
+            config.post_body = parse_sanitization_policy("post_body", policy.as_str())?;
+        }
+
+        if let Some(policy) = env_toml.get("comment_body") {
+            config.comment_body = parse_sanitization_policy("comment_body", policy.as_str())?;
+        }
+
+        if let Some(policy) = env_toml.get("user_name") {
+            config.user_name = parse_sanitization_policy("user_name", policy.as_str())?;
+        }
+
+        if let Some(policy) = env_toml.get("tag_name") {
+            config.tag_name = parse_sanitization_policy("tag_name", policy.as_str())?;
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_sanitization_policy(key: &str,
+                             value: Option<&str>)
+                             -> Result<SanitizationPolicy, ConfigFileError> {
+    value.and_then(|value| value.parse().ok())
+        .ok_or_else(|| {
+            ConfigFileError::Parsing(format!("invalid {}: must be either 'plain_text' or \
+                                              'limited_html'",
+                                             key))
+        })
+}
+
 #[derive(Debug)]
 pub enum ConfigError {
-    Db(DbConfigError),
+    Db(ConfigFileError),
+    Auth(ConfigFileError),
+    Spam(ConfigFileError),
+    Trash(ConfigFileError),
+    Deletion(ConfigFileError),
+    Sanitization(ConfigFileError),
 }
 
-impl From<DbConfigError> for ConfigError {
-    fn from(err: DbConfigError) -> ConfigError {
-        ConfigError::Db(err)
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Db(ref err) => write!(f, "Db config error: {}", err),
+            ConfigError::Auth(ref err) => write!(f, "Auth config error: {}", err),
+            ConfigError::Spam(ref err) => write!(f, "Spam config error: {}", err),
+            ConfigError::Trash(ref err) => write!(f, "Trash config error: {}", err),
+            ConfigError::Deletion(ref err) => write!(f, "Deletion config error: {}", err),
+            ConfigError::Sanitization(ref err) => {
+                write!(f, "Sanitization config error: {}", err)
+            }
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Config {
     db: DbConfig,
+    auth: AuthConfig,
+    spam: SpamConfig,
+    trash: TrashConfig,
+    deletion: DeletionConfig,
+    sanitization: SanitizationConfig,
 }
 
 impl Config {
     pub fn load(environment: &Env) -> Result<Config, ConfigError> {
-        let database_config = DbConfig::load(environment)?;
+        let database_config = DbConfig::load(environment).map_err(ConfigError::Db)?;
+        let auth_config = AuthConfig::load(environment).map_err(ConfigError::Auth)?;
+        let spam_config = SpamConfig::load(environment).map_err(ConfigError::Spam)?;
+        let trash_config = TrashConfig::load(environment).map_err(ConfigError::Trash)?;
+        let deletion_config = DeletionConfig::load(environment).map_err(ConfigError::Deletion)?;
+        let sanitization_config = SanitizationConfig::load(environment)
+            .map_err(ConfigError::Sanitization)?;
 
-        Ok(Config { db: database_config })
+        Ok(Config {
+            db: database_config,
+            auth: auth_config,
+            spam: spam_config,
+            trash: trash_config,
+            deletion: deletion_config,
+            sanitization: sanitization_config,
+        })
     }
 
     pub fn db(&self) -> &DbConfig {
         &self.db
     }
+
+    pub fn auth(&self) -> &AuthConfig {
+        &self.auth
+    }
+
+    pub fn spam(&self) -> &SpamConfig {
+        &self.spam
+    }
+
+    pub fn trash(&self) -> &TrashConfig {
+        &self.trash
+    }
+
+    pub fn deletion(&self) -> &DeletionConfig {
+        &self.deletion
+    }
+
+    pub fn sanitization(&self) -> &SanitizationConfig {
+        &self.sanitization
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn requires_a_secret() {
+        assert!(check_secret(&Env::Development, None).is_err());
+        assert!(check_secret(&Env::Production, None).is_err());
+    }
+
+    #[test]
+    fn accepts_placeholder_secrets_in_development_and_test_only() {
+        let placeholder = || Some(String::from("production-secret-change-me"));
+
+        assert!(check_secret(&Env::Development, placeholder()).is_ok());
+        assert!(check_secret(&Env::Test, placeholder()).is_ok());
+        assert!(check_secret(&Env::Staging, placeholder()).is_err());
+        assert!(check_secret(&Env::Production, placeholder()).is_err());
+        assert!(check_secret(&Env::Production, Some(String::from(" "))).is_err());
+    }
+
+    #[test]
+    fn accepts_a_real_secret_in_production() {
+        let secret = String::from("8kWq3zN1vR0pLx7T");
+
+        assert_eq!(check_secret(&Env::Production, Some(secret.clone())).unwrap(), secret);
+    }
 }
@@ -9,6 +9,7 @@ use r2d2_diesel::ConnectionManager;
 use config::DbConfig;
 
 use diesel::result::Error as DieselError;
+use diesel::result::TransactionError;
 use r2d2::GetTimeout;
 
 #[derive(Debug)]
@@ -52,6 +53,15 @@ impl From<DieselError> for DbError {
     }
 }
 
+impl From<TransactionError<DieselError>> for DbError {
+    fn from(err: TransactionError<DieselError>) -> DbError {
+        match err {
+            TransactionError::CouldntCreateTransaction(err) |
+            TransactionError::UserReturnedError(err) => DbError::Db(err),
+        }
+    }
+}
+
 impl From<InitializationError> for DbError {
     fn from(err: InitializationError) -> DbError {
         DbError::PoolInitialization(err)
@@ -69,7 +79,7 @@ pub struct Db {
     pub config: DbConfig,
 }
 
-type DbPool = Pool<ConnectionManager<PgConnection>>;
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 
 impl Db {
     pub fn new(config: DbConfig) -> Db {
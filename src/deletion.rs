@@ -0,0 +1,244 @@
+use std::str::FromStr;
+use std::string::ToString;
+use std::fmt;
+use std::error;
+
+use chrono::NaiveDateTime;
+use diesel;
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::result::Error as DieselError;
+
+use config::DeletionConfig;
+use models::{Post, User};
+use schema::{comments, posts, users};
+use timestamps;
+
+/// What happens to the records depending on one that gets deleted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeletePolicy {
+    /// Delete the dependents along with it.
+    Cascade,
+    /// Hand the dependents over to the "deleted user" placeholder.
+    Reassign,
+    /// Refuse to delete while there are dependents.
+    Restrict,
+}
+
+#[derive(Debug)]
+pub enum ParseDeletePolicyError {
+    UnknownPolicy,
+}
+
+impl fmt::Display for ParseDeletePolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseDeletePolicyError::UnknownPolicy => write!(f, "Unknown delete policy"),
+        }
+    }
+}
+
+impl FromStr for DeletePolicy {
+    type Err = ParseDeletePolicyError;
+
+    fn from_str(s: &str) -> Result<DeletePolicy, Self::Err> {
+        match s {
+            "cascade" => Ok(DeletePolicy::Cascade),
+            "reassign" => Ok(DeletePolicy::Reassign),
+            "restrict" => Ok(DeletePolicy::Restrict),
+            _ => Err(ParseDeletePolicyError::UnknownPolicy),
+        }
+    }
+}
+
+impl ToString for DeletePolicy {
+    fn to_string(&self) -> String {
+        match *self {
+            DeletePolicy::Cascade => String::from("cascade"),
+            DeletePolicy::Reassign => String::from("reassign"),
+            DeletePolicy::Restrict => String::from("restrict"),
+        }
+    }
+}
+
+/// Records of one kind standing in the way of a restricted delete.
+#[derive(Debug, Serialize)]
+pub struct Dependents {
+    pub kind: &'static str,
+    pub ids: Vec<i32>,
+}
+
+impl fmt::Display for Dependents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ids = self.ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        write!(f, "{} {}", self.kind, ids.join(", "))
+    }
+}
+
+#[derive(Debug)]
+pub enum DeletionError {
+    Db(DieselError),
+    Restricted(Vec<Dependents>),
+    Placeholder,
+}
+
+impl fmt::Display for DeletionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeletionError::Db(ref err) => write!(f, "Db error {}", err),
+            DeletionError::Restricted(ref dependents) => {
+                let dependents = dependents.iter()
+                    .map(|dependents| dependents.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "still referenced by {}", dependents.join("; "))
+            }
+            DeletionError::Placeholder => {
+                write!(f, "the deleted user placeholder cannot be deleted")
+            }
+        }
+    }
+}
+
+impl error::Error for DeletionError {
+    fn description(&self) -> &str {
+        match *self {
+            DeletionError::Db(ref err) => err.description(),
+            DeletionError::Restricted(_) => "still referenced by other records",
+            DeletionError::Placeholder => "the deleted user placeholder cannot be deleted",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            DeletionError::Db(ref err) => Some(err),
+            DeletionError::Restricted(_) => None,
+            DeletionError::Placeholder => None,
+        }
+    }
+}
+
+impl From<DieselError> for DeletionError {
+    fn from(err: DieselError) -> DeletionError {
+        DeletionError::Db(err)
+    }
+}
+
+/// Soft-deletes `user`, first dealing with their posts and comments as
+/// `config` says. Meant to run inside a transaction: a restricted delete
+/// fails half way, leaving the rollback to undo the rest. Whatever gets
+/// deleted along with the user shares their deletion time, so restoring the
+/// user can bring it back.
+pub fn delete_user(conn: &PgConnection,
+                   user: &User,
+                   config: &DeletionConfig)
+                   -> Result<(), DeletionError> {
+    if user.placeholder {
+        return Err(DeletionError::Placeholder);
+    }
+
+    let now = timestamps::now();
+    let mut restricted = Vec::new();
+
+    let owned_posts = posts::table.filter(posts::user_id.eq(user.id))
+        .filter(posts::deleted_at.is_null())
+        .load::<Post>(conn)?;
+    match config.user_posts {
+        DeletePolicy::Cascade => {
+            for post in &owned_posts {
+                delete_post_at(conn, post, config, now)?;
+            }
+        }
+        DeletePolicy::Reassign => {
+            let placeholder = placeholder(conn)?;
+            diesel::update(posts::table.filter(posts::user_id.eq(user.id)))
+                .set(posts::user_id.eq(placeholder.id))
+                .execute(conn)?;
+        }
+        DeletePolicy::Restrict if !owned_posts.is_empty() => {
+            restricted.push(Dependents {
+                kind: "posts",
+                ids: owned_posts.iter().map(|post| post.id).collect(),
+            })
+        }
+        DeletePolicy::Restrict => {}
+    }
+
+    let comment_ids = comments::table.select(comments::id)
+        .filter(comments::user_id.eq(user.id))
+        .filter(comments::deleted_at.is_null())
+        .load::<i32>(conn)?;
+    match config.user_comments {
+        DeletePolicy::Cascade => {
+            diesel::update(comments::table.filter(comments::id.eq_any(comment_ids)))
+                .set(comments::deleted_at.eq(now))
+                .execute(conn)?;
+        }
+        DeletePolicy::Reassign => {
+            let placeholder = placeholder(conn)?;
+            diesel::update(comments::table.filter(comments::user_id.eq(user.id)))
+                .set(comments::user_id.eq(placeholder.id))
+                .execute(conn)?;
+        }
+        DeletePolicy::Restrict if !comment_ids.is_empty() => {
+            restricted.push(Dependents {
+                kind: "comments",
+                ids: comment_ids,
+            })
+        }
+        DeletePolicy::Restrict => {}
+    }
+
+    if !restricted.is_empty() {
+        return Err(DeletionError::Restricted(restricted));
+    }
+
+    diesel::update(users::table.find(user.id))
+        .set(users::deleted_at.eq(now))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Soft-deletes `post`, either with its comments or, when restricted, only if
+/// it has none. Comments cannot be reassigned to another post. The comments
+/// get the same deletion time as the post, which is how restoring the post
+/// tells them apart from the ones deleted before.
+pub fn delete_post(conn: &PgConnection,
+                   post: &Post,
+                   config: &DeletionConfig)
+                   -> Result<(), DeletionError> {
+    delete_post_at(conn, post, config, timestamps::now())
+}
+
+fn delete_post_at(conn: &PgConnection,
+                  post: &Post,
+                  config: &DeletionConfig,
+                  now: NaiveDateTime)
+                  -> Result<(), DeletionError> {
+    let comment_ids = comments::table.select(comments::id)
+        .filter(comments::post_id.eq(post.id))
+        .filter(comments::deleted_at.is_null())
+        .load::<i32>(conn)?;
+
+    if config.post_comments == DeletePolicy::Restrict && !comment_ids.is_empty() {
+        return Err(DeletionError::Restricted(vec![Dependents {
+                                                       kind: "comments",
+                                                       ids: comment_ids,
+                                                   }]));
+    }
+
+    diesel::update(comments::table.filter(comments::id.eq_any(comment_ids)))
+        .set(comments::deleted_at.eq(now))
+        .execute(conn)?;
+    diesel::update(posts::table.find(post.id))
+        .set(posts::deleted_at.eq(now))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// The account content gets reassigned to, flagged as such and seeded by the
+/// migrations.
+fn placeholder(conn: &PgConnection) -> QueryResult<User> {
+    users::table.filter(users::placeholder.eq(true)).first::<User>(conn)
+}
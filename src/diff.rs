@@ -0,0 +1,104 @@
+use std::cmp;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A line of a diff, kept, added or removed.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Line {
+    pub change: Change,
+    pub text: String,
+}
+
+/// Line-based diff turning `old` into `new`, built from their longest common
+/// subsequence of lines. Removals come before insertions on each change.
+pub fn lines(old: &str, new: &str) -> Vec<Line> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // common[i][j] is the length of the LCS of old[i..] and new[j..].
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                cmp::max(common[i + 1][j], common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(line(Change::Equal, old[i]));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            diff.push(line(Change::Delete, old[i]));
+            i += 1;
+        } else {
+            diff.push(line(Change::Insert, new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|text| line(Change::Delete, text)));
+    diff.extend(new[j..].iter().map(|text| line(Change::Insert, text)));
+
+    diff
+}
+
+fn line(change: Change, text: &str) -> Line {
+    Line {
+        change: change,
+        text: text.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(diff: &[Line]) -> Vec<(Change, &str)> {
+        diff.iter().map(|line| (line.change, line.text.as_str())).collect()
+    }
+
+    #[test]
+    fn keeps_equal_lines() {
+        assert_eq!(changes(&lines("a\nb", "a\nb")),
+                   vec![(Change::Equal, "a"), (Change::Equal, "b")]);
+    }
+
+    #[test]
+    fn removes_before_inserting() {
+        assert_eq!(changes(&lines("a\nb\nc", "a\nx\nc")),
+                   vec![(Change::Equal, "a"),
+                        (Change::Delete, "b"),
+                        (Change::Insert, "x"),
+                        (Change::Equal, "c")]);
+    }
+
+    #[test]
+    fn handles_empty_sides() {
+        assert_eq!(changes(&lines("", "a\nb")),
+                   vec![(Change::Insert, "a"), (Change::Insert, "b")]);
+        assert_eq!(changes(&lines("a", "")), vec![(Change::Delete, "a")]);
+        assert!(lines("", "").is_empty());
+    }
+
+    #[test]
+    fn keeps_the_longest_common_subsequence() {
+        assert_eq!(changes(&lines("a\nb\nc\nd", "b\nd\ne")),
+                   vec![(Change::Delete, "a"),
+                        (Change::Equal, "b"),
+                        (Change::Delete, "c"),
+                        (Change::Equal, "d"),
+                        (Change::Insert, "e")]);
+    }
+}
@@ -2,25 +2,71 @@ use std::fmt;
 use std::error;
 
 use diesel::result::Error as DieselError;
+use diesel::result::TransactionError;
+use diesel::result::DatabaseErrorInformation;
+use diesel::result::DatabaseErrorKind::UniqueViolation;
 use r2d2::{GetTimeout, InitializationError};
 
+use rocket::{Request, Response};
+use rocket::http::Status;
 use rocket::response;
 use rocket::response::Responder;
 
+use auth::AuthError;
 use db::DbError;
+use deletion::DeletionError;
+use validation::ValidationErrors;
 use endpoints::helpers::*;
+use endpoints::request_id::request_id;
+
+// The request field each named constraint guards, by constraint kind.
+const UNIQUE_CONSTRAINTS: &'static [(&'static str, &'static str)] =
+    &[("users_username_unique", "username"),
+      ("users_email_unique", "email"),
+      ("posts_slug_unique", "slug"),
+      ("post_slugs_slug_unique", "slug"),
+      ("tags_name_unique", "name"),
+      ("post_tags_tag_unique", "tags")];
+const FOREIGN_KEY_CONSTRAINTS: &'static [(&'static str, &'static str)] =
+    &[("posts_user_id_fkey", "user_id"),
+      ("comments_user_id_fkey", "user_id"),
+      ("comments_post_id_fkey", "post_id"),
+      ("comments_parent_id_fkey", "parent_id"),
+      ("post_slugs_post_id_fkey", "post_id"),
+      ("post_tags_post_id_fkey", "post_id"),
+      ("post_tags_tag_id_fkey", "tags"),
+      ("post_revisions_post_id_fkey", "post_id"),
+      ("post_revisions_user_id_fkey", "user_id")];
+const CHECK_CONSTRAINTS: &'static [(&'static str, &'static str)] =
+    &[("users_role_check", "role"), ("comments_status_check", "status")];
+const SERIALIZATION_FAILURE_MESSAGE: &'static str = "could not serialize access";
+
+// Seconds a client should wait before retrying when the Db pool is exhausted.
+const POOL_TIMEOUT_RETRY_AFTER: u32 = 1;
 
 pub type EndpointResult<T> = Result<T, EndpointError>;
 
 #[derive(Debug)]
 pub enum EndpointError {
     Db(DbError),
+    Auth(AuthError),
+    Forbidden,
+    BadRequest(String),
+    Conflict(String),
+    Deletion(DeletionError),
+    Validation(ValidationErrors),
 }
 
 impl fmt::Display for EndpointError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             EndpointError::Db(ref err) => write!(f, "Db error {}", err),
+            EndpointError::Auth(ref err) => write!(f, "Auth error {}", err),
+            EndpointError::Forbidden => write!(f, "Forbidden"),
+            EndpointError::BadRequest(ref msg) => write!(f, "Bad request: {}", msg),
+            EndpointError::Conflict(ref msg) => write!(f, "Conflict: {}", msg),
+            EndpointError::Deletion(ref err) => write!(f, "Deletion error {}", err),
+            EndpointError::Validation(ref err) => write!(f, "Validation error {}", err),
         }
     }
 }
@@ -29,12 +75,24 @@ impl error::Error for EndpointError {
     fn description(&self) -> &str {
         match *self {
             EndpointError::Db(ref err) => err.description(),
+            EndpointError::Auth(ref err) => err.description(),
+            EndpointError::Forbidden => "forbidden",
+            EndpointError::BadRequest(ref msg) => msg,
+            EndpointError::Conflict(ref msg) => msg,
+            EndpointError::Deletion(ref err) => err.description(),
+            EndpointError::Validation(ref err) => err.description(),
         }
     }
 
     fn cause(&self) -> Option<&error::Error> {
         match *self {
             EndpointError::Db(ref err) => Some(err),
+            EndpointError::Auth(ref err) => Some(err),
+            EndpointError::Forbidden => None,
+            EndpointError::BadRequest(_) => None,
+            EndpointError::Conflict(_) => None,
+            EndpointError::Deletion(ref err) => Some(err),
+            EndpointError::Validation(ref err) => Some(err),
         }
     }
 }
@@ -45,12 +103,39 @@ impl From<DbError> for EndpointError {
     }
 }
 
+impl From<AuthError> for EndpointError {
+    fn from(err: AuthError) -> EndpointError {
+        EndpointError::Auth(err)
+    }
+}
+
+impl From<ValidationErrors> for EndpointError {
+    fn from(err: ValidationErrors) -> EndpointError {
+        EndpointError::Validation(err)
+    }
+}
+
 impl From<DieselError> for EndpointError {
     fn from(err: DieselError) -> EndpointError {
         EndpointError::Db(DbError::from(err))
     }
 }
 
+impl From<DeletionError> for EndpointError {
+    fn from(err: DeletionError) -> EndpointError {
+        EndpointError::Deletion(err)
+    }
+}
+
+impl<E: Into<EndpointError>> From<TransactionError<E>> for EndpointError {
+    fn from(err: TransactionError<E>) -> EndpointError {
+        match err {
+            TransactionError::CouldntCreateTransaction(err) => EndpointError::from(err),
+            TransactionError::UserReturnedError(err) => err.into(),
+        }
+    }
+}
+
 impl From<GetTimeout> for EndpointError {
     fn from(err: GetTimeout) -> EndpointError {
         EndpointError::Db(DbError::from(err))
@@ -63,12 +148,100 @@ impl From<InitializationError> for EndpointError {
     }
 }
 
-
 impl<'r> Responder<'r> for EndpointError {
-    fn respond(self) -> response::Result<'r> {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        warn!("[{}] {}", request_id(request).unwrap_or("-"), self);
+
         match self {
-            EndpointError::Db(DbError::Db(DieselError::NotFound)) => Ok(not_found_json_response()),
-            _ => Ok(ise_json_response()),
+            EndpointError::Db(err) => Ok(db_error_response(request, err)),
+            EndpointError::Auth(AuthError::Db(err)) => Ok(db_error_response(request, err)),
+            EndpointError::Auth(AuthError::PasswordHashing(_)) |
+            EndpointError::Auth(AuthError::MissingState(_)) => Ok(ise_json_response(request)),
+            EndpointError::Auth(_) => Ok(unauthorized_json_response(request)),
+            EndpointError::Forbidden => Ok(forbidden_json_response(request)),
+            EndpointError::BadRequest(msg) => {
+                Ok(bad_request_json_response(request, "bad_request", &msg))
+            }
+            EndpointError::Conflict(msg) => {
+                Ok(conflict_json_response(request, "conflict", None, &msg))
+            }
+            EndpointError::Deletion(err) => Ok(deletion_error_response(request, err)),
+            EndpointError::Validation(errors) => {
+                Ok(unprocessable_entity_json_response(request, "validation_failed", json!(errors)))
+            }
         }
     }
 }
+
+fn db_error_response<'r>(request: &Request, err: DbError) -> Response<'r> {
+    match err {
+        DbError::Db(DieselError::NotFound) => not_found_json_response(request),
+        DbError::Db(DieselError::DatabaseError(UniqueViolation, info)) => {
+            let field = constraint_field(&*info, UNIQUE_CONSTRAINTS);
+            let message = match field {
+                Some(ref field) => format!("{} is already taken", field),
+                None => String::from("resource already exists"),
+            };
+            conflict_json_response(request, "unique_violation", field, &message)
+        }
+        DbError::Db(DieselError::DatabaseError(_, info)) => {
+            database_error_response(request, &*info)
+        }
+        DbError::PoolTimeout(_) => {
+            service_unavailable_json_response(request, POOL_TIMEOUT_RETRY_AFTER)
+        }
+        _ => ise_json_response(request),
+    }
+}
+
+/// A restricted delete lists what is in the way in `details.dependents`, as
+/// `{"kind": "posts", "ids": [...]}` entries.
+fn deletion_error_response<'r>(request: &Request, err: DeletionError) -> Response<'r> {
+    let message = err.to_string();
+
+    match err {
+        DeletionError::Db(err) => db_error_response(request, DbError::from(err)),
+        DeletionError::Restricted(dependents) => {
+            error_json_response(request,
+                                Status::Conflict,
+                                "restricted",
+                                &message,
+                                json!({"dependents": dependents}))
+        }
+        DeletionError::Placeholder => conflict_json_response(request, "conflict", None, &message),
+    }
+}
+
+/// Classifies the database errors Diesel does not have a kind for by the
+/// constraint they violated.
+fn database_error_response<'r>(request: &Request,
+                               info: &DatabaseErrorInformation)
+                               -> Response<'r> {
+    if let Some(field) = constraint_field(info, FOREIGN_KEY_CONSTRAINTS) {
+        let mut errors = ValidationErrors::new();
+        errors.add(&field, "references a record that does not exist");
+        unprocessable_entity_json_response(request, "foreign_key_violation", json!(errors))
+    } else if let Some(field) = constraint_field(info, CHECK_CONSTRAINTS) {
+        let mut errors = ValidationErrors::new();
+        errors.add(&field, "is not an allowed value");
+        unprocessable_entity_json_response(request, "check_violation", json!(errors))
+    } else if info.message().starts_with(SERIALIZATION_FAILURE_MESSAGE) {
+        conflict_json_response(request,
+                               "serialization_failure",
+                               None,
+                               "the request conflicted with a concurrent one, retry it")
+    } else {
+        ise_json_response(request)
+    }
+}
+
+/// Looks up the field guarded by the violated constraint among `constraints`.
+fn constraint_field(info: &DatabaseErrorInformation,
+                    constraints: &[(&str, &str)])
+                    -> Option<String> {
+    info.constraint_name().and_then(|name| {
+        constraints.iter()
+            .find(|&&(constraint, _)| constraint == name)
+            .map(|&(_, field)| field.to_owned())
+    })
+}
@@ -1,123 +1,542 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
 use diesel::prelude::*;
 use diesel;
+use diesel::pg::{Pg, PgConnection};
+use diesel::query_builder::BoxedSelectStatement;
+use diesel::expression::dsl::sql;
+use diesel::types::Bool;
+use diesel::result::Error as DieselError;
 
 use rocket::{State, Response};
+use rocket::Request;
 use rocket::http::Status;
-use rocket_contrib::{JSON, Value};
+use rocket::response::{self, Responder};
+use rocket_contrib::Json;
 
-use db::{Db, DbError};
+use db::Db;
 use models::Comment;
+use models::CommentNode;
 use models::NewComment;
+use models::NewCommentRecord;
 use models::UpdatedComment;
 use models::Post;
 use models::User;
+use roles::Capability;
 use schema::posts::dsl::*;
 use schema::comments::dsl::*;
 use schema::comments;
 use schema::users::dsl::*;
-
-use endpoint_error::EndpointResult;
+use schema::posts;
+use schema::users;
+use moderation::{self, ModerationStatus};
+use sanitization::Sanitized;
+use spam::{Candidate, SpamFilters};
+use timestamps;
+
+use auth::CurrentUser;
+use endpoint_error::{EndpointError, EndpointResult};
+use validation::{Validate, ValidationErrors};
+use endpoints::authorization::{self, authorize, require, restrict_changeset};
 use endpoints::helpers::*;
-use endpoints::pagination::Pagination;
+use endpoints::listing::{self, ListQuery, Listing};
+use endpoints::pagination::Page;
+
+const SORTABLE_FIELDS: &'static [&'static str] = &["id", "published", "user_id", "post_id",
+                                                    "created_at", "updated_at"];
+const FILTERABLE_FIELDS: &'static [&'static str] = &["user_id", "post_id", "published", "q"];
+
+const DEFAULT_MAX_DEPTH: i32 = 5;
+const MAX_DEPTH_LIMIT: i32 = 20;
+
+type CommentsQuery<'a> = BoxedSelectStatement<'a, comments::SqlType, comments::table, Pg>;
+
+/// Filters accepted by the comment listings, `q` matching the body.
+/// Deleted comments are always left out.
+#[derive(Default)]
+struct CommentFilter {
+    user_id: Option<i32>,
+    post_id: Option<i32>,
+    published: Option<bool>,
+    q: Option<String>,
+}
+
+impl CommentFilter {
+    fn parse(listing: &Listing) -> EndpointResult<CommentFilter> {
+        let mut filter = CommentFilter::default();
+
+        for &(ref field, ref value) in listing.filters() {
+            match field.as_str() {
+                "user_id" => filter.user_id = Some(listing::parse_value(field, value)?),
+                "post_id" => filter.post_id = Some(listing::parse_value(field, value)?),
+                "published" => filter.published = Some(listing::parse_value(field, value)?),
+                "q" => filter.q = Some(value.clone()),
+                _ => {}
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn apply<'a>(&self, mut query: CommentsQuery<'a>) -> CommentsQuery<'a> {
+        query = query.filter(comments::deleted_at.is_null());
+        if let Some(value) = self.user_id {
+            query = query.filter(comments::user_id.eq(value));
+        }
+        if let Some(value) = self.post_id {
+            query = query.filter(comments::post_id.eq(value));
+        }
+        if let Some(value) = self.published {
+            query = query.filter(comments::published.eq(value));
+        }
+        if let Some(ref value) = self.q {
+            query = query.filter(listing::lower(comments::body)
+                .like(listing::contains_pattern(value)));
+        }
+
+        query
+    }
+}
 
 #[get("/comments", format = "application/json")]
-fn index(db: State<Db>) -> EndpointResult<JSON<Value>> {
-    let results = all_comments(&db, None)?;
+fn index(db: State<Db>) -> EndpointResult<Page<Comment>> {
+    let page = all_comments(&db, &Listing::default())?;
 
-    Ok(JSON(json!(results)))
+    Ok(page)
 }
 
-#[get("/comments?<pagination>", format = "application/json")]
-fn index_paginated(db: State<Db>, pagination: Pagination) -> EndpointResult<JSON<Value>> {
-    let results = all_comments(&db, Some(pagination))?;
+#[get("/comments?<query>", format = "application/json")]
+fn index_paginated(db: State<Db>, query: ListQuery) -> EndpointResult<Page<Comment>> {
+    let listing = query.parse(SORTABLE_FIELDS, FILTERABLE_FIELDS)?;
+    let page = all_comments(&db, &listing)?;
 
-    Ok(JSON(json!(results)))
+    Ok(page)
 }
 
 #[post("/comments", data = "<new_comment>", format = "application/json")]
-fn create(db: State<Db>, new_comment: JSON<NewComment>) -> EndpointResult<JSON<Comment>> {
+fn create(db: State<Db>,
+          spam_filters: State<SpamFilters>,
+          current_user: CurrentUser,
+          remote: Option<SocketAddr>,
+          new_comment: Sanitized<NewComment>)
+          -> EndpointResult<Json<Comment>> {
     let conn = &*db.pool().get()?;
 
-    let comment = diesel::insert(&new_comment.0).into(comments::table)
+    let current_user = current_user.0;
+    require(&current_user, Capability::WriteComments)?;
+
+    let mut new_comment = new_comment.0;
+    new_comment.validate()?;
+    if !authorization::is_admin(&current_user) {
+        new_comment.user_id = current_user.id;
+    }
+    visible_post(conn, new_comment.post_id, Some(&current_user))?;
+    if let Some(parent_id) = new_comment.parent_id {
+        check_parent(conn, parent_id, new_comment.post_id)?;
+    }
+
+    let ip_address = remote.map(|remote| remote.ip().to_string());
+    let candidate = Candidate {
+        body: &new_comment.body,
+        user_id: new_comment.user_id,
+        post_id: new_comment.post_id,
+        ip_address: ip_address.as_ref().map(String::as_str),
+    };
+
+    let initial_status = screen(conn, &spam_filters, &candidate, &current_user)?;
+    let record = NewCommentRecord {
+        body: &new_comment.body,
+        user_id: new_comment.user_id,
+        post_id: new_comment.post_id,
+        parent_id: new_comment.parent_id,
+        status: initial_status.to_string(),
+        published: initial_status.is_published(),
+        ip_address: ip_address.clone(),
+        body_html: new_comment.body_html.clone(),
+    };
+
+    let comment = diesel::insert(&record).into(comments::table)
         .get_result::<Comment>(conn)?;
 
-    Ok(JSON(comment))
+    Ok(Json(comment))
 }
 
 #[get("/comments/<id>", format = "application/json")]
-fn show(id: i32, db: State<Db>) -> EndpointResult<JSON<Comment>> {
+fn show(id: i32,
+        db: State<Db>,
+        current_user: Option<CurrentUser>)
+        -> EndpointResult<Json<Comment>> {
     let conn = &*db.pool().get()?;
 
-    let comment = comments.find(id).first::<Comment>(conn)?;
+    let comment = comments.find(id).filter(comments::deleted_at.is_null()).first::<Comment>(conn)?;
+    if !authorization::can_view_comment(current_user.as_ref().map(|user| &user.0), &comment) {
+        return Err(DieselError::NotFound.into());
+    }
 
-    Ok(JSON(comment))
+    Ok(Json(comment))
 }
 
+/// Updates a comment. A new body goes through the spam filters and the
+/// moderation rules again, as if it had just been posted, unless a moderator
+/// already rejected the comment.
 #[put("/comments/<id>", data = "<updated_comment>", format = "application/json")]
 fn update(db: State<Db>,
+          spam_filters: State<SpamFilters>,
+          current_user: CurrentUser,
           id: i32,
-          updated_comment: JSON<UpdatedComment>)
-          -> EndpointResult<JSON<Comment>> {
+          updated_comment: Sanitized<UpdatedComment>)
+          -> EndpointResult<Json<Comment>> {
     let conn = &*db.pool().get()?;
 
-    let comment = diesel::update(comments.find(id)).set(&updated_comment.0)
+    let comment = comments.find(id).filter(comments::deleted_at.is_null()).first::<Comment>(conn)?;
+    authorize(&current_user.0, &comment)?;
+
+    let mut updated_comment = updated_comment.0;
+    updated_comment.validate()?;
+    restrict_changeset(&current_user.0, &mut updated_comment);
+    if let Some(new_post_id) = updated_comment.post_id {
+        if new_post_id != comment.post_id && is_threaded(conn, &comment)? {
+            let mut errors = ValidationErrors::new();
+            errors.add("post_id", "cannot move a comment that is part of a thread");
+            return Err(errors.into());
+        }
+    }
+
+    let new_status = match updated_comment.body {
+        Some(ref body) if *body != comment.body &&
+                          comment.status() != ModerationStatus::Rejected => {
+            let candidate = Candidate {
+                body: body,
+                user_id: updated_comment.user_id.unwrap_or(comment.user_id),
+                post_id: updated_comment.post_id.unwrap_or(comment.post_id),
+                ip_address: comment.ip_address.as_ref().map(String::as_str),
+            };
+            Some(screen(conn, &spam_filters, &candidate, &current_user.0)?)
+        }
+        _ => None,
+    };
+    if let Some(new_status) = new_status {
+        updated_comment.status = Some(new_status.to_string());
+        updated_comment.published = Some(new_status.is_published());
+    }
+
+    let comment = diesel::update(comments.find(id))
+        .set((&updated_comment, comments::updated_at.eq(timestamps::now())))
         .get_result::<Comment>(conn)?;
 
-    Ok(JSON(comment))
+    Ok(Json(comment))
 }
 
 #[delete("/comments/<id>", format = "application/json")]
-fn destroy(id: i32, db: State<Db>) -> EndpointResult<Response> {
+fn destroy(id: i32, db: State<Db>, current_user: CurrentUser) -> EndpointResult<Response> {
     let conn = &*db.pool().get()?;
 
-    diesel::delete(comments.find(id)).get_result::<Comment>(conn)?;
+    let comment = comments.find(id).filter(comments::deleted_at.is_null()).first::<Comment>(conn)?;
+    authorize(&current_user.0, &comment)?;
+
+    diesel::update(comments.find(id))
+        .set(comments::deleted_at.eq(timestamps::now()))
+        .get_result::<Comment>(conn)?;
 
     Response::build().status(Status::NoContent).ok()
 }
 
 #[get("/posts/<id>/comments", format = "application/json")]
-fn post_comments_index(id: i32, db: State<Db>) -> EndpointResult<JSON<Value>> {
-    let conn = &*db.pool().get()?;
+fn post_comments_index(id: i32,
+                       db: State<Db>,
+                       current_user: Option<CurrentUser>)
+                       -> EndpointResult<Page<Comment>> {
+    let viewer = current_user.as_ref().map(|user| &user.0);
+    let page = post_comments(&db, id, &Listing::default(), viewer)?;
+
+    Ok(page)
+}
 
-    let post = posts.find(id).first::<Post>(conn)?;
+/// With `tree=true` the listing pages through top-level comments, each
+/// carrying its replies nested up to `max_depth` levels deep.
+#[get("/posts/<id>/comments?<query>", format = "application/json")]
+fn post_comments_index_paginated(id: i32,
+                                 db: State<Db>,
+                                 mut query: ListQuery,
+                                 current_user: Option<CurrentUser>)
+                                 -> EndpointResult<CommentListing> {
+    let tree = match query.take("tree") {
+        Some(value) => listing::parse_value::<bool>("tree", &value)?,
+        None => false,
+    };
+    let max_depth = match query.take("max_depth") {
+        Some(value) => listing::parse_value::<i32>("max_depth", &value)?,
+        None => DEFAULT_MAX_DEPTH,
+    };
+
+    let listing = query.parse(SORTABLE_FIELDS, FILTERABLE_FIELDS)?;
+    let viewer = current_user.as_ref().map(|user| &user.0);
+
+    if !tree {
+        return Ok(CommentListing::Flat(post_comments(&db, id, &listing, viewer)?));
+    }
 
-    let results = Comment::belonging_to(&post).get_results::<Comment>(conn)?;
+    if max_depth < 0 || max_depth > MAX_DEPTH_LIMIT {
+        return Err(EndpointError::BadRequest(format!("'max_depth' must be between 0 and {}",
+                                                     MAX_DEPTH_LIMIT)));
+    }
 
-    Ok(JSON(json!(results)))
+    Ok(CommentListing::Tree(post_comment_tree(&db, id, &listing, max_depth, viewer)?))
 }
 
 #[get("/users/<id>/comments", format = "application/json")]
-fn user_comments_index(id: i32, db: State<Db>) -> EndpointResult<JSON<Value>> {
-    let conn = &*db.pool().get()?;
+fn user_comments_index(id: i32, db: State<Db>) -> EndpointResult<Page<Comment>> {
+    let page = user_comments(&db, id, &Listing::default())?;
 
-    let user = users.find(id).first::<User>(conn)?;
+    Ok(page)
+}
 
-    let results = Comment::belonging_to(&user).get_results::<Comment>(conn)?;
+#[get("/users/<id>/comments?<query>", format = "application/json")]
+fn user_comments_index_paginated(id: i32,
+                                 db: State<Db>,
+                                 query: ListQuery)
+                                 -> EndpointResult<Page<Comment>> {
+    let listing = query.parse(SORTABLE_FIELDS, FILTERABLE_FIELDS)?;
+    let page = user_comments(&db, id, &listing)?;
 
-    Ok(JSON(json!(results)))
+    Ok(page)
 }
 
 #[get("/posts/<id>/comments/<comment_id>", format = "application/json")]
-fn post_comment_show(id: i32, comment_id: i32, db: State<Db>) -> EndpointResult<JSON<Comment>> {
+fn post_comment_show(id: i32,
+                     comment_id: i32,
+                     db: State<Db>,
+                     current_user: Option<CurrentUser>)
+                     -> EndpointResult<Json<Comment>> {
     let conn = &*db.pool().get()?;
+    let viewer = current_user.as_ref().map(|user| &user.0);
+
+    visible_post(conn, id, viewer)?;
 
     let comment = comments.filter(post_id.eq(id).and(comments::id.eq(&comment_id)))
+        .filter(comments::deleted_at.is_null())
         .first::<Comment>(conn)?;
+    if !authorization::can_view_comment(viewer, &comment) {
+        return Err(DieselError::NotFound.into());
+    }
+
+    Ok(Json(comment))
+}
+
+/// A post's comments, either flat or as reply trees.
+pub enum CommentListing {
+    Flat(Page<Comment>),
+    Tree(Page<CommentNode>),
+}
 
-    Ok(JSON(comment))
+impl<'r> Responder<'r> for CommentListing {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        match self {
+            CommentListing::Flat(page) => page.respond_to(request),
+            CommentListing::Tree(page) => page.respond_to(request),
+        }
+    }
 }
 
-fn all_comments(db: &Db, pagination: Option<Pagination>) -> Result<Vec<Comment>, DbError> {
-    let mut query = comments.filter(comments::published.eq(true)).into_boxed();
+/// Fails with a validation error unless `parent_id` is a comment on `post_id`.
+fn check_parent(conn: &PgConnection, parent_id: i32, for_post_id: i32) -> EndpointResult<()> {
+    let parent = comments.find(parent_id)
+        .filter(comments::deleted_at.is_null())
+        .first::<Comment>(conn)
+        .optional()?;
+
+    let mut errors = ValidationErrors::new();
+    match parent {
+        None => errors.add("parent_id", "does not exist"),
+        Some(ref parent) if parent.post_id != for_post_id => {
+            errors.add("parent_id", "must belong to the same post")
+        }
+        Some(_) => {}
+    }
+
+    errors.into_result().map_err(EndpointError::from)
+}
 
-    if let Some(pagination) = pagination {
-        let page = pagination.get_page();
-        let per_page = pagination.get_per_page();
-        query = query.limit(per_page).offset(per_page * (page - 1));
+/// Whether `comment` is a reply or has replies.
+fn is_threaded(conn: &PgConnection, comment: &Comment) -> EndpointResult<bool> {
+    if comment.parent_id.is_some() {
+        return Ok(true);
     }
 
+    let replies = comments.filter(comments::parent_id.eq(comment.id))
+        .count()
+        .get_result::<i64>(conn)?;
+
+    Ok(replies > 0)
+}
+
+fn all_comments(db: &Db, listing: &Listing) -> EndpointResult<Page<Comment>> {
     let conn = &*db.pool().get()?;
+    let filter = CommentFilter::parse(listing)?;
 
-    query.load::<Comment>(conn)
-        .map_err(|err| DbError::from(err))
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         comments::id,
+                         listing.order("comments", "id"),
+                         filter.apply(on_public_posts(comments.into_boxed()
+                             .filter(comments::published.eq(true)))),
+                         Comment)?;
+
+    Ok(page)
+}
+
+/// The status `candidate`, written by `author`, gets: the spam filters' verdict
+/// if they have one, the usual moderation rules otherwise.
+fn screen(conn: &PgConnection,
+          spam_filters: &SpamFilters,
+          candidate: &Candidate,
+          author: &User)
+          -> EndpointResult<ModerationStatus> {
+    let status = match spam_filters.verdict(conn, candidate)? {
+        Some(verdict) => verdict,
+        None => moderation::initial_status(conn, author)?,
+    };
+
+    Ok(status)
+}
+
+/// Narrows `query` to comments on published, non deleted posts. For the
+/// listings spanning several posts, which `visible_post` cannot guard.
+fn on_public_posts<'a>(query: CommentsQuery<'a>) -> CommentsQuery<'a> {
+    let public_post_ids = posts::table.select(posts::id)
+        .filter(posts::published.eq(true))
+        .filter(posts::deleted_at.is_null());
+
+    query.filter(comments::post_id.eq_any(public_post_ids))
+}
+
+/// Loads post `id`, answering 404 like `posts::show` when `viewer` may not see
+/// it, so its comments do not leak drafts.
+fn visible_post(conn: &PgConnection, id: i32, viewer: Option<&User>) -> EndpointResult<Post> {
+    let post = posts.find(id).filter(posts::deleted_at.is_null()).first::<Post>(conn)?;
+    if !authorization::can_view(viewer, &post) {
+        return Err(DieselError::NotFound.into());
+    }
+
+    Ok(post)
+}
+
+fn post_comments(db: &Db,
+                 id: i32,
+                 listing: &Listing,
+                 viewer: Option<&User>)
+                 -> EndpointResult<Page<Comment>> {
+    let conn = &*db.pool().get()?;
+    let filter = CommentFilter::parse(listing)?;
+
+    let post = visible_post(conn, id, viewer)?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         comments::id,
+                         listing.order("comments", "id"),
+                         filter.apply(Comment::belonging_to(&post)
+                             .filter(comments::published.eq(true))
+                             .into_boxed()),
+                         Comment)?;
+
+    Ok(page)
+}
+
+fn user_comments(db: &Db, id: i32, listing: &Listing) -> EndpointResult<Page<Comment>> {
+    let conn = &*db.pool().get()?;
+    let filter = CommentFilter::parse(listing)?;
+
+    let user = users.find(id).filter(users::deleted_at.is_null()).first::<User>(conn)?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         comments::id,
+                         listing.order("comments", "id"),
+                         filter.apply(on_public_posts(Comment::belonging_to(&user)
+                             .filter(comments::published.eq(true))
+                             .into_boxed())),
+                         Comment)?;
+
+    Ok(page)
+}
+
+fn post_comment_tree(db: &Db,
+                     id: i32,
+                     listing: &Listing,
+                     max_depth: i32,
+                     viewer: Option<&User>)
+                     -> EndpointResult<Page<CommentNode>> {
+    let conn = &*db.pool().get()?;
+    let filter = CommentFilter::parse(listing)?;
+
+    let post = visible_post(conn, id, viewer)?;
+
+    let roots = paginate!(conn,
+                          listing.pagination(),
+                          comments::id,
+                          listing.order("comments", "id"),
+                          filter.apply(Comment::belonging_to(&post)
+                              .filter(comments::published.eq(true))
+                              .filter(comments::parent_id.is_null())
+                              .into_boxed()),
+                          Comment)?;
+
+    let root_ids = roots.items().iter().map(|comment| comment.id).collect::<Vec<_>>();
+    let replies = replies_of(conn, &root_ids, max_depth)?;
+
+    Ok(roots.map(|roots| build_tree(roots, replies)))
+}
+
+/// Every published, non deleted reply under `root_ids` down to `max_depth` levels, loaded in
+/// a single query through a recursive CTE. Only integers are interpolated.
+fn replies_of(conn: &PgConnection, root_ids: &[i32], max_depth: i32) -> QueryResult<Vec<Comment>> {
+    if root_ids.is_empty() || max_depth == 0 {
+        return Ok(Vec::new());
+    }
+
+    let root_ids = root_ids.iter().map(i32::to_string).collect::<Vec<_>>().join(", ");
+    let thread = format!("comments.id IN (\
+                          WITH RECURSIVE replies (id, depth) AS (\
+                            SELECT c.id, 1 FROM comments c \
+                            WHERE c.parent_id IN ({}) \
+                            AND c.published AND c.deleted_at IS NULL \
+                            UNION ALL \
+                            SELECT c.id, replies.depth + 1 FROM comments c \
+                            JOIN replies ON c.parent_id = replies.id \
+                            WHERE replies.depth < {} \
+                            AND c.published AND c.deleted_at IS NULL\
+                          ) SELECT id FROM replies)",
+                         root_ids,
+                         max_depth);
+
+    comments.filter(sql::<Bool>(&thread))
+        .order(comments::id.asc())
+        .load::<Comment>(conn)
+}
+
+/// Nests `replies` under `roots`, keeping both in id order.
+fn build_tree(roots: Vec<Comment>, replies: Vec<Comment>) -> Vec<CommentNode> {
+    let mut children = HashMap::new();
+    for reply in replies {
+        if let Some(parent_id) = reply.parent_id {
+            children.entry(parent_id).or_insert_with(Vec::new).push(reply);
+        }
+    }
+
+    roots.into_iter().map(|root| node(root, &mut children)).collect()
+}
+
+fn node(comment: Comment, children: &mut HashMap<i32, Vec<Comment>>) -> CommentNode {
+    let replies = children.remove(&comment.id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|reply| node(reply, children))
+        .collect();
+
+    CommentNode {
+        comment: comment,
+        replies: replies,
+    }
 }
@@ -1,3 +1,9 @@
 pub mod posts;
 pub mod users;
 pub mod comments;
+pub mod sessions;
+pub mod search;
+pub mod tags;
+pub mod moderation;
+pub mod trash;
+pub mod revisions;
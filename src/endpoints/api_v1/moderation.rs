@@ -0,0 +1,149 @@
+use diesel::prelude::*;
+use diesel;
+use diesel::pg::Pg;
+use diesel::query_builder::BoxedSelectStatement;
+
+use rocket::State;
+use rocket_contrib::Json;
+
+use db::Db;
+use models::Comment;
+use moderation::ModerationStatus;
+use roles::Capability;
+use schema::comments::dsl::*;
+use schema::comments;
+use timestamps;
+
+use auth::CurrentUser;
+use endpoint_error::{EndpointError, EndpointResult};
+use endpoints::authorization::require;
+use endpoints::listing::{self, ListQuery, Listing};
+use endpoints::pagination::Page;
+
+const SORTABLE_FIELDS: &'static [&'static str] = &["id", "user_id", "post_id", "created_at"];
+const FILTERABLE_FIELDS: &'static [&'static str] = &["status", "post_id"];
+
+type CommentsQuery<'a> = BoxedSelectStatement<'a, comments::SqlType, comments::table, Pg>;
+
+/// Filters accepted by the moderation queue.
+struct QueueFilter {
+    status: ModerationStatus,
+    post_id: Option<i32>,
+}
+
+impl QueueFilter {
+    fn parse(listing: &Listing) -> EndpointResult<QueueFilter> {
+        let mut filter = QueueFilter {
+            status: ModerationStatus::Pending,
+            post_id: None,
+        };
+
+        for &(ref field, ref value) in listing.filters() {
+            match field.as_str() {
+                "status" => filter.status = listing::parse_value(field, value)?,
+                "post_id" => filter.post_id = Some(listing::parse_value(field, value)?),
+                _ => {}
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn apply<'a>(&self, mut query: CommentsQuery<'a>) -> CommentsQuery<'a> {
+        query = query.filter(comments::deleted_at.is_null())
+            .filter(comments::status.eq(self.status.to_string()));
+        if let Some(value) = self.post_id {
+            query = query.filter(comments::post_id.eq(value));
+        }
+
+        query
+    }
+}
+
+/// The moderation queue: pending comments, oldest first, unless another
+/// `status` is asked for.
+#[get("/moderation/comments", format = "application/json")]
+fn index(db: State<Db>, current_user: CurrentUser) -> EndpointResult<Page<Comment>> {
+    require(&current_user.0, Capability::ModerateComments)?;
+
+    let page = queued_comments(&db, &Listing::default())?;
+
+    Ok(page)
+}
+
+#[get("/moderation/comments?<query>", format = "application/json")]
+fn index_paginated(db: State<Db>,
+                   current_user: CurrentUser,
+                   query: ListQuery)
+                   -> EndpointResult<Page<Comment>> {
+    require(&current_user.0, Capability::ModerateComments)?;
+
+    let listing = query.parse(SORTABLE_FIELDS, FILTERABLE_FIELDS)?;
+    let page = queued_comments(&db, &listing)?;
+
+    Ok(page)
+}
+
+#[post("/comments/<id>/approve", format = "application/json")]
+fn approve(id: i32, db: State<Db>, current_user: CurrentUser) -> EndpointResult<Json<Comment>> {
+    let comment = moderate(&db, &current_user, id, ModerationStatus::Approved)?;
+
+    Ok(Json(comment))
+}
+
+#[post("/comments/<id>/reject", format = "application/json")]
+fn reject(id: i32, db: State<Db>, current_user: CurrentUser) -> EndpointResult<Json<Comment>> {
+    let comment = moderate(&db, &current_user, id, ModerationStatus::Rejected)?;
+
+    Ok(Json(comment))
+}
+
+#[post("/comments/<id>/spam", format = "application/json")]
+fn mark_as_spam(id: i32,
+                db: State<Db>,
+                current_user: CurrentUser)
+                -> EndpointResult<Json<Comment>> {
+    let comment = moderate(&db, &current_user, id, ModerationStatus::Spam)?;
+
+    Ok(Json(comment))
+}
+
+/// Moves a comment to `target`, publishing it only when approved.
+fn moderate(db: &Db,
+            current_user: &CurrentUser,
+            id: i32,
+            target: ModerationStatus)
+            -> EndpointResult<Comment> {
+    let conn = &*db.pool().get()?;
+
+    require(&current_user.0, Capability::ModerateComments)?;
+
+    let comment = comments.find(id).filter(deleted_at.is_null()).first::<Comment>(conn)?;
+    if !comment.status().can_become(target) {
+        return Err(EndpointError::Conflict(format!("a {} comment cannot be marked as {}",
+                                                   comment.status,
+                                                   target.to_string())));
+    }
+
+    let comment = diesel::update(comments.find(id))
+        .set((status.eq(target.to_string()),
+              published.eq(target.is_published()),
+              updated_at.eq(timestamps::now())))
+        .get_result::<Comment>(conn)?;
+
+    Ok(comment)
+}
+
+fn queued_comments(db: &Db, listing: &Listing) -> EndpointResult<Page<Comment>> {
+    let conn = &*db.pool().get()?;
+    let filter = QueueFilter::parse(listing)?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         comments::id,
+                         listing.order("comments", "id"),
+                         filter.apply(comments.into_boxed()),
+                         Comment)?;
+
+    Ok(page)
+}
@@ -1,106 +1,436 @@
 use diesel::prelude::*;
 use diesel;
+use diesel::pg::Pg;
+use diesel::query_builder::BoxedSelectStatement;
+use diesel::result::Error as DieselError;
+use chrono::NaiveDateTime;
 
 use rocket::{State, Response};
 use rocket::http::Status;
-use rocket_contrib::{JSON, Value};
+use rocket_contrib::Json;
 
-use db::{Db, DbError};
+use config::Config;
+use db::Db;
+use deletion;
 use models::Post;
 use models::NewPost;
+use models::NewPostRecord;
+use models::PostSlug;
 use models::UpdatedPost;
+use models::UpdatedPostRecord;
 use models::User;
+use roles::Capability;
 use schema::posts::dsl::*;
 use schema::posts;
+use schema::post_slugs;
+use schema::post_tags;
 use schema::users::dsl::*;
+use schema::users;
+use revisions;
+use sanitization::Sanitized;
+use slugs;
+use tagging;
+use timestamps;
 
+use auth::CurrentUser;
 use endpoint_error::EndpointResult;
+use validation::Validate;
+use endpoints::authorization::{self, authorize, require, restrict_changeset};
 use endpoints::helpers::*;
-use endpoints::pagination::Pagination;
+use endpoints::listing::{self, ListQuery, Listing};
+use endpoints::pagination::Page;
+
+const SORTABLE_FIELDS: &'static [&'static str] = &["id", "title", "published", "user_id",
+                                                    "created_at", "updated_at", "published_at"];
+const FILTERABLE_FIELDS: &'static [&'static str] = &["user_id", "published", "q"];
+
+type PostsQuery<'a> = BoxedSelectStatement<'a, posts::SqlType, posts::table, Pg>;
+
+/// Filters accepted by the post listings, `q` matching the title or body.
+/// Deleted posts are always left out.
+#[derive(Default)]
+struct PostFilter {
+    user_id: Option<i32>,
+    published: Option<bool>,
+    q: Option<String>,
+    published_only: bool,
+}
+
+impl PostFilter {
+    fn parse(listing: &Listing) -> EndpointResult<PostFilter> {
+        let mut filter = PostFilter::default();
+
+        for &(ref field, ref value) in listing.filters() {
+            match field.as_str() {
+                "user_id" => filter.user_id = Some(listing::parse_value(field, value)?),
+                "published" => filter.published = Some(listing::parse_value(field, value)?),
+                "q" => filter.q = Some(value.clone()),
+                _ => {}
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn apply<'a>(&self, mut query: PostsQuery<'a>) -> PostsQuery<'a> {
+        query = query.filter(posts::deleted_at.is_null());
+        if let Some(value) = self.user_id {
+            query = query.filter(posts::user_id.eq(value));
+        }
+        if let Some(value) = self.published {
+            query = query.filter(posts::published.eq(value));
+        }
+        if self.published_only {
+            query = query.filter(posts::published.eq(true));
+        }
+        if let Some(ref value) = self.q {
+            let pattern = listing::contains_pattern(value);
+            query = query.filter(listing::lower(posts::title)
+                .like(pattern.clone())
+                .or(listing::lower(posts::body).like(pattern)));
+        }
+
+        query
+    }
+}
 
 #[get("/posts", format = "application/json")]
-fn index(db: State<Db>) -> EndpointResult<JSON<Value>> {
-    let results = published_posts(&*db, None)?;
+fn index(db: State<Db>) -> EndpointResult<Page<Post>> {
+    let page = published_posts(&*db, &Listing::default())?;
 
-    Ok(JSON(json!(results)))
+    Ok(page)
 }
 
-#[get("/posts?<pagination>", format = "application/json")]
-fn index_paginated(db: State<Db>, pagination: Pagination) -> EndpointResult<JSON<Value>> {
-    let results = published_posts(&*db, Some(&pagination))?;
+#[get("/posts?<query>", format = "application/json")]
+fn index_paginated(db: State<Db>, query: ListQuery) -> EndpointResult<Page<Post>> {
+    let listing = query.parse(SORTABLE_FIELDS, FILTERABLE_FIELDS)?;
+    let page = published_posts(&*db, &listing)?;
 
-    Ok(JSON(json!(results)))
+    Ok(page)
 }
 
 #[post("/posts", data = "<new_post>", format = "application/json")]
-fn create(db: State<Db>, new_post: JSON<NewPost>) -> EndpointResult<JSON<Post>> {
+fn create(db: State<Db>,
+          current_user: CurrentUser,
+          new_post: Sanitized<NewPost>)
+          -> EndpointResult<Json<Post>> {
     let conn = &*db.pool().get()?;
 
-    let post = diesel::insert(&new_post.0).into(posts::table)
-        .get_result::<Post>(conn)?;
+    let current_user = current_user.0;
+    require(&current_user, Capability::WritePosts)?;
+
+    let mut new_post = new_post.0;
+    new_post.validate()?;
+    if !authorization::is_admin(&current_user) {
+        new_post.user_id = Some(current_user.id);
+    }
 
-    Ok(JSON(post))
+    let record = NewPostRecord {
+        title: &new_post.title,
+        body: &new_post.body,
+        user_id: new_post.user_id,
+        publish_at: new_post.publish_at,
+        slug: slugs::unique_slug(conn, &slugs::slugify(&new_post.title), None)?,
+        body_html: new_post.body_html.clone(),
+    };
+
+    let post = conn.transaction::<_, DieselError, _>(|| {
+        let post = diesel::insert(&record).into(posts::table)
+            .get_result::<Post>(conn)?;
+        if let Some(ref names) = new_post.tags {
+            tagging::set_post_tags(conn, post.id, names)?;
+        }
+
+        Ok(post)
+    })?;
+
+    Ok(Json(post))
 }
 
 #[get("/posts/<id>", format = "application/json")]
-fn show(id: i32, db: State<Db>) -> EndpointResult<JSON<Post>> {
+fn show(id: i32,
+        db: State<Db>,
+        current_user: Option<CurrentUser>)
+        -> EndpointResult<Json<Post>> {
+    let conn = &*db.pool().get()?;
+
+    let post = posts.find(id).filter(posts::deleted_at.is_null()).first::<Post>(conn)?;
+    if !authorization::can_view(current_user.as_ref().map(|user| &user.0), &post) {
+        return Err(DieselError::NotFound.into());
+    }
+
+    Ok(Json(post))
+}
+
+/// Looks a post up by its current slug, or redirects (301) to it when `slug`
+/// is one the post had before being renamed.
+///
+/// Ranked below the `/posts/<id>/...` routes it would otherwise collide with.
+#[get("/posts/by-slug/<slug>", format = "application/json", rank = 2)]
+fn show_by_slug(slug: String,
+                db: State<Db>,
+                current_user: Option<CurrentUser>)
+                -> EndpointResult<Response<'static>> {
     let conn = &*db.pool().get()?;
 
-    let post = posts.find(id).first::<Post>(conn)?;
+    let post = posts.filter(posts::slug.eq(&slug))
+        .filter(posts::deleted_at.is_null())
+        .first::<Post>(conn)
+        .optional()?;
 
-    Ok(JSON(post))
+    let (post, renamed) = match post {
+        Some(post) => (post, false),
+        None => {
+            let old_slug = post_slugs::table.filter(post_slugs::slug.eq(&slug))
+                .first::<PostSlug>(conn)?;
+            let post = posts.find(old_slug.post_id)
+                .filter(posts::deleted_at.is_null())
+                .first::<Post>(conn)?;
+
+            (post, true)
+        }
+    };
+    // Checked before redirecting too, or the redirect would reveal the
+    // current slug of a draft.
+    if !authorization::can_view(current_user.as_ref().map(|user| &user.0), &post) {
+        return Err(DieselError::NotFound.into());
+    }
+
+    if renamed {
+        Ok(redirect_to_slug(&post))
+    } else {
+        Ok(ok_json_response(json!(post)))
+    }
+}
+
+// Slugs are plain `[a-z0-9-]`, so the relative reference needs no encoding.
+fn redirect_to_slug(post: &Post) -> Response<'static> {
+    Response::build()
+        .status(Status::MovedPermanently)
+        .raw_header("Location", post.slug.clone())
+        .finalize()
 }
 
 #[put("/posts/<id>", data = "<updated_post>", format = "application/json")]
-fn update(db: State<Db>, id: i32, updated_post: JSON<UpdatedPost>) -> EndpointResult<JSON<Post>> {
+fn update(db: State<Db>,
+          current_user: CurrentUser,
+          id: i32,
+          updated_post: Sanitized<UpdatedPost>)
+          -> EndpointResult<Json<Post>> {
     let conn = &*db.pool().get()?;
 
-    let post = diesel::update(posts.find(id)).set(&updated_post.0)
+    let post = posts.find(id).filter(posts::deleted_at.is_null()).first::<Post>(conn)?;
+    authorize(&current_user.0, &post)?;
+
+    let mut updated_post = updated_post.0;
+    updated_post.validate()?;
+    restrict_changeset(&current_user.0, &mut updated_post);
+
+    let mut record = UpdatedPostRecord {
+        title: updated_post.title.as_ref().map(String::as_str),
+        body: updated_post.body.as_ref().map(String::as_str),
+        user_id: updated_post.user_id,
+        publish_at: updated_post.publish_at,
+        slug: None,
+        body_html: updated_post.body_html.clone(),
+    };
+
+    if let Some(ref new_title) = updated_post.title {
+        let new_slug = slugs::unique_slug(conn, &slugs::slugify(new_title), Some(post.id))?;
+        if new_slug != post.slug {
+            record.slug = Some(new_slug);
+        }
+    }
+
+    let post = conn.transaction(|| {
+        revisions::record(conn, &post, current_user.0.id)?;
+        if let Some(ref new_slug) = record.slug {
+            slugs::retire(conn, &post, new_slug)?;
+        }
+
+        if let Some(ref names) = updated_post.tags {
+            tagging::set_post_tags(conn, post.id, names)?;
+        }
+
+        diesel::update(posts.find(id))
+            .set((&record, posts::updated_at.eq(timestamps::now())))
+            .get_result::<Post>(conn)
+    })?;
+
+    Ok(Json(post))
+}
+
+#[post("/posts/<id>/publish", format = "application/json")]
+fn publish(id: i32, db: State<Db>, current_user: CurrentUser) -> EndpointResult<Json<Post>> {
+    let conn = &*db.pool().get()?;
+
+    let post = posts.find(id).filter(posts::deleted_at.is_null()).first::<Post>(conn)?;
+    authorize(&current_user.0, &post)?;
+
+    if post.published {
+        return Ok(Json(post));
+    }
+
+    let now = timestamps::now();
+    let post = diesel::update(posts.find(id))
+        .set((published.eq(true),
+              published_at.eq(now),
+              publish_at.eq(None::<NaiveDateTime>),
+              posts::updated_at.eq(now)))
         .get_result::<Post>(conn)?;
 
-    Ok(JSON(post))
+    Ok(Json(post))
 }
 
+#[post("/posts/<id>/unpublish", format = "application/json")]
+fn unpublish(id: i32, db: State<Db>, current_user: CurrentUser) -> EndpointResult<Json<Post>> {
+    let conn = &*db.pool().get()?;
+
+    let post = posts.find(id).filter(posts::deleted_at.is_null()).first::<Post>(conn)?;
+    authorize(&current_user.0, &post)?;
+
+    let post = diesel::update(posts.find(id))
+        .set((published.eq(false),
+              published_at.eq(None::<NaiveDateTime>),
+              publish_at.eq(None::<NaiveDateTime>),
+              posts::updated_at.eq(timestamps::now())))
+        .get_result::<Post>(conn)?;
+
+    Ok(Json(post))
+}
+
+/// Deletes the post and deals with its comments following the configured
+/// policy, all in one transaction.
 #[delete("/posts/<id>", format = "application/json")]
-fn destroy(id: i32, db: State<Db>) -> EndpointResult<Response> {
+fn destroy(id: i32,
+           db: State<Db>,
+           config: State<Config>,
+           current_user: CurrentUser)
+           -> EndpointResult<Response> {
     let conn = &*db.pool().get()?;
 
-    diesel::delete(posts.find(id)).get_result::<Post>(conn)?;
+    let post = posts.find(id).filter(posts::deleted_at.is_null()).first::<Post>(conn)?;
+    authorize(&current_user.0, &post)?;
+
+    conn.transaction(|| deletion::delete_post(conn, &post, config.deletion()))?;
 
     Response::build().status(Status::NoContent).ok()
 }
 
 #[get("/users/<id>/posts", format = "application/json")]
-fn user_posts_index(id: i32, db: State<Db>) -> EndpointResult<Response> {
-    let conn = &*db.pool().get()?;
+fn user_posts_index(id: i32,
+                    db: State<Db>,
+                    current_user: Option<CurrentUser>)
+                    -> EndpointResult<Page<Post>> {
+    let viewer = current_user.as_ref().map(|user| &user.0);
+    let page = user_posts(&db, id, &Listing::default(), viewer)?;
+
+    Ok(page)
+}
 
-    let user = users.find(id).first::<User>(conn)?;
-    let results = Post::belonging_to(&user).load::<Post>(conn)?;
+#[get("/users/<id>/posts?<query>", format = "application/json")]
+fn user_posts_index_paginated(id: i32,
+                              db: State<Db>,
+                              query: ListQuery,
+                              current_user: Option<CurrentUser>)
+                              -> EndpointResult<Page<Post>> {
+    let listing = query.parse(SORTABLE_FIELDS, FILTERABLE_FIELDS)?;
+    let viewer = current_user.as_ref().map(|user| &user.0);
+    let page = user_posts(&db, id, &listing, viewer)?;
 
-    Ok(ok_json_response(json!(results)))
+    Ok(page)
 }
 
 #[get("/users/<id>/posts/<post_id>", format = "application/json")]
-fn user_post_show(id: i32, post_id: i32, db: State<Db>) -> EndpointResult<JSON<Post>> {
+fn user_post_show(id: i32,
+                  post_id: i32,
+                  db: State<Db>,
+                  current_user: Option<CurrentUser>)
+                  -> EndpointResult<Json<Post>> {
     let conn = &*db.pool().get()?;
 
     let post = posts.filter(user_id.eq(id).and(posts::id.eq(&post_id)))
+        .filter(posts::deleted_at.is_null())
         .first::<Post>(conn)?;
+    if !authorization::can_view(current_user.as_ref().map(|user| &user.0), &post) {
+        return Err(DieselError::NotFound.into());
+    }
 
-    Ok(JSON(post))
+    Ok(Json(post))
 }
 
-fn published_posts(db: &Db, pagination: Option<&Pagination>) -> Result<Vec<Post>, DbError> {
-    let mut query = posts.filter(published.eq(true)).into_boxed();
+#[get("/tags/<name>/posts", format = "application/json")]
+fn tag_posts_index(name: String, db: State<Db>) -> EndpointResult<Page<Post>> {
+    let page = tag_posts(&db, &name, &Listing::default())?;
 
-    if let Some(pagination) = pagination {
-        let page = pagination.get_page();
-        let per_page = pagination.get_per_page();
-        query = query.limit(per_page).offset(per_page * (page - 1));
-    }
+    Ok(page)
+}
+
+#[get("/tags/<name>/posts?<query>", format = "application/json")]
+fn tag_posts_index_paginated(name: String,
+                             db: State<Db>,
+                             query: ListQuery)
+                             -> EndpointResult<Page<Post>> {
+    let listing = query.parse(SORTABLE_FIELDS, FILTERABLE_FIELDS)?;
+    let page = tag_posts(&db, &name, &listing)?;
+
+    Ok(page)
+}
+
+fn published_posts(db: &Db, listing: &Listing) -> EndpointResult<Page<Post>> {
+    let conn = &*db.pool().get()?;
+    let filter = PostFilter::parse(listing)?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         posts::id,
+                         listing.order("posts", "id"),
+                         filter.apply(posts.filter(published.eq(true)).into_boxed()),
+                         Post)?;
+
+    Ok(page)
+}
 
+/// A user's posts; drafts are only listed for the user themselves and for
+/// whoever may edit any post.
+fn user_posts(db: &Db,
+              id: i32,
+              listing: &Listing,
+              viewer: Option<&User>)
+              -> EndpointResult<Page<Post>> {
     let conn = &*db.pool().get()?;
+    let mut filter = PostFilter::parse(listing)?;
+
+    let user = users.find(id).filter(users::deleted_at.is_null()).first::<User>(conn)?;
+    filter.published_only = !viewer.map_or(false, |viewer| {
+        viewer.id == user.id || viewer.can(Capability::EditAnyPost)
+    });
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         posts::id,
+                         listing.order("posts", "id"),
+                         filter.apply(Post::belonging_to(&user).into_boxed()),
+                         Post)?;
+
+    Ok(page)
+}
+
+fn tag_posts(db: &Db, name: &str, listing: &Listing) -> EndpointResult<Page<Post>> {
+    let conn = &*db.pool().get()?;
+    let filter = PostFilter::parse(listing)?;
+
+    let tag = tagging::find_by_name(conn, name)?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         posts::id,
+                         listing.order("posts", "id"),
+                         filter.apply(posts.filter(published.eq(true))
+                             .filter(posts::id.eq_any(post_tags::table.select(post_tags::post_id)
+                                 .filter(post_tags::tag_id.eq(tag.id))))
+                             .into_boxed()),
+                         Post)?;
 
-    query.load::<Post>(conn)
-        .map_err(DbError::from)
+    Ok(page)
 }
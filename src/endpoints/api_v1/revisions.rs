@@ -0,0 +1,161 @@
+use diesel::prelude::*;
+use diesel;
+use diesel::pg::PgConnection;
+
+use rocket::State;
+use rocket_contrib::{Json, Value};
+
+use config::Config;
+use db::Db;
+use diff;
+use markdown;
+use models::{Post, PostRevision, UpdatedPostRecord};
+use revisions;
+use sanitization;
+use schema::post_revisions;
+use schema::posts;
+use slugs;
+use timestamps;
+
+use auth::CurrentUser;
+use endpoint_error::EndpointResult;
+use endpoints::authorization::authorize;
+use endpoints::listing::{ListQuery, Listing};
+use endpoints::pagination::Page;
+
+const SORTABLE_FIELDS: &'static [&'static str] = &["id", "user_id", "created_at"];
+
+/// The revisions of a post, oldest first. Only those who may edit the post get
+/// to see what it used to say.
+#[get("/posts/<id>/revisions", format = "application/json")]
+fn index(id: i32, db: State<Db>, current_user: CurrentUser) -> EndpointResult<Page<PostRevision>> {
+    let page = post_revisions(&db, &current_user, id, &Listing::default())?;
+
+    Ok(page)
+}
+
+#[get("/posts/<id>/revisions?<query>", format = "application/json")]
+fn index_paginated(id: i32,
+                   db: State<Db>,
+                   current_user: CurrentUser,
+                   query: ListQuery)
+                   -> EndpointResult<Page<PostRevision>> {
+    let listing = query.parse(SORTABLE_FIELDS, &[])?;
+    let page = post_revisions(&db, &current_user, id, &listing)?;
+
+    Ok(page)
+}
+
+#[get("/posts/<id>/revisions/<revision_id>", format = "application/json")]
+fn show(id: i32,
+        revision_id: i32,
+        db: State<Db>,
+        current_user: CurrentUser)
+        -> EndpointResult<Json<PostRevision>> {
+    let conn = &*db.pool().get()?;
+
+    let post = find_post(conn, id)?;
+    authorize(&current_user.0, &post)?;
+
+    let revision = revisions::find(conn, post.id, revision_id)?;
+
+    Ok(Json(revision))
+}
+
+/// Line-based diff of the title and body from revision `from` to `to`, as they
+/// were stored.
+#[get("/posts/<id>/revisions/<from>/diff/<to>", format = "application/json")]
+fn compare(id: i32,
+           from: i32,
+           to: i32,
+           db: State<Db>,
+           current_user: CurrentUser)
+           -> EndpointResult<Json<Value>> {
+    let conn = &*db.pool().get()?;
+
+    let post = find_post(conn, id)?;
+    authorize(&current_user.0, &post)?;
+
+    let from = revisions::find(conn, post.id, from)?;
+    let to = revisions::find(conn, post.id, to)?;
+
+    Ok(Json(json!({
+        "from": from.id,
+        "to": to.id,
+        "title": diff::lines(&from.title, &to.title),
+        "body": diff::lines(&from.body, &to.body),
+    })))
+}
+
+/// Brings back the title and body of a revision. The rollback is an update
+/// like any other, so the content it replaces becomes a revision too.
+#[post("/posts/<id>/revisions/<revision_id>/rollback", format = "application/json")]
+fn rollback(id: i32,
+            revision_id: i32,
+            db: State<Db>,
+            config: State<Config>,
+            current_user: CurrentUser)
+            -> EndpointResult<Json<Post>> {
+    let conn = &*db.pool().get()?;
+
+    let post = find_post(conn, id)?;
+    authorize(&current_user.0, &post)?;
+
+    let revision = revisions::find(conn, post.id, revision_id)?;
+    let policies = config.sanitization();
+    let title = sanitization::clean(policies.post_title, &revision.title);
+
+    let mut record = UpdatedPostRecord {
+        title: Some(&title),
+        body: Some(&revision.body),
+        user_id: None,
+        publish_at: None,
+        slug: None,
+        body_html: Some(markdown::render(&revision.body, policies.post_body)),
+    };
+
+    let new_slug = slugs::unique_slug(conn, &slugs::slugify(&title), Some(post.id))?;
+    if new_slug != post.slug {
+        record.slug = Some(new_slug);
+    }
+
+    let post = conn.transaction(|| {
+        revisions::record(conn, &post, current_user.0.id)?;
+        if let Some(ref new_slug) = record.slug {
+            slugs::retire(conn, &post, new_slug)?;
+        }
+
+        diesel::update(posts::table.find(id))
+            .set((&record, posts::updated_at.eq(timestamps::now())))
+            .get_result::<Post>(conn)
+    })?;
+
+    Ok(Json(post))
+}
+
+fn find_post(conn: &PgConnection, id: i32) -> QueryResult<Post> {
+    posts::table.find(id)
+        .filter(posts::deleted_at.is_null())
+        .first::<Post>(conn)
+}
+
+fn post_revisions(db: &Db,
+                  current_user: &CurrentUser,
+                  id: i32,
+                  listing: &Listing)
+                  -> EndpointResult<Page<PostRevision>> {
+    let conn = &*db.pool().get()?;
+
+    let post = find_post(conn, id)?;
+    authorize(&current_user.0, &post)?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         post_revisions::id,
+                         listing.order("post_revisions", "id"),
+                         post_revisions::table.filter(post_revisions::post_id.eq(post.id))
+                             .into_boxed(),
+                         PostRevision)?;
+
+    Ok(page)
+}
@@ -0,0 +1,118 @@
+use diesel::prelude::*;
+
+use rocket::State;
+use rocket_contrib::{Json, Value};
+
+use db::Db;
+use search::{self, english, plainto_tsquery, ts_headline, ts_rank, TsVectorExpressionMethods};
+use search::HEADLINE_OPTIONS;
+use search::schema::{comments, posts};
+
+use endpoint_error::{EndpointError, EndpointResult};
+use endpoints::listing::ListQuery;
+
+const SEARCH_PARAMS: &'static [&'static str] = &["q"];
+
+/// A matching post. `snippet` is HTML, the body escaped with the matched
+/// terms in `<mark>`.
+#[derive(Queryable, Serialize)]
+pub struct PostHit {
+    pub id: i32,
+    pub title: String,
+    pub snippet: String,
+    pub rank: f32,
+}
+
+/// A matching comment, `snippet` built like the one of `PostHit`.
+#[derive(Queryable, Serialize)]
+pub struct CommentHit {
+    pub id: i32,
+    pub post_id: i32,
+    pub snippet: String,
+    pub rank: f32,
+}
+
+/// Full-text search over published, non deleted posts and comments, best
+/// matches first.
+///
+/// Both result sets are paged with the same `page`/`per_page`; cursors make no
+/// sense for a listing ordered by rank.
+#[get("/search?<query>", format = "application/json")]
+fn index(db: State<Db>, query: ListQuery) -> EndpointResult<Json<Value>> {
+    let conn = &*db.pool().get()?;
+
+    let listing = query.parse(&[], SEARCH_PARAMS)?;
+    let pagination = listing.pagination();
+    if pagination.is_keyset() {
+        return Err(EndpointError::BadRequest(String::from("search results cannot be paged by \
+                                                           cursor, use 'page'/'per_page'")));
+    }
+
+    let terms = match listing.filters().iter().find(|&&(ref field, _)| field == "q") {
+        Some(&(_, ref value)) if !value.trim().is_empty() => value.as_str(),
+        _ => return Err(EndpointError::BadRequest(String::from("'q' is required"))),
+    };
+
+    let published_post_ids = posts::table.select(posts::id)
+        .filter(posts::published.eq(true))
+        .filter(posts::deleted_at.is_null());
+
+    let posts_total = posts::table.filter(posts::published.eq(true))
+        .filter(posts::deleted_at.is_null())
+        .filter(posts::search_vector.matches(plainto_tsquery(english(), terms)))
+        .count()
+        .get_result::<i64>(conn)?;
+    let post_hits = posts::table.filter(posts::published.eq(true))
+        .filter(posts::deleted_at.is_null())
+        .filter(posts::search_vector.matches(plainto_tsquery(english(), terms)))
+        .select((posts::id,
+                 posts::title,
+                 ts_headline(english(), posts::body, plainto_tsquery(english(), terms),
+                             HEADLINE_OPTIONS),
+                 ts_rank(posts::search_vector, plainto_tsquery(english(), terms))))
+        .order((ts_rank(posts::search_vector, plainto_tsquery(english(), terms)).desc(),
+                posts::id.asc()))
+        .limit(pagination.get_per_page())
+        .offset(pagination.get_offset())
+        .load::<PostHit>(conn)?
+        .into_iter()
+        .map(|hit| PostHit { snippet: search::highlight(&hit.snippet), ..hit })
+        .collect::<Vec<_>>();
+
+    let comments_total = comments::table.filter(comments::published.eq(true))
+        .filter(comments::deleted_at.is_null())
+        .filter(comments::post_id.eq_any(published_post_ids))
+        .filter(comments::search_vector.matches(plainto_tsquery(english(), terms)))
+        .count()
+        .get_result::<i64>(conn)?;
+    let comment_hits = comments::table.filter(comments::published.eq(true))
+        .filter(comments::deleted_at.is_null())
+        .filter(comments::post_id.eq_any(published_post_ids))
+        .filter(comments::search_vector.matches(plainto_tsquery(english(), terms)))
+        .select((comments::id,
+                 comments::post_id,
+                 ts_headline(english(), comments::body, plainto_tsquery(english(), terms),
+                             HEADLINE_OPTIONS),
+                 ts_rank(comments::search_vector, plainto_tsquery(english(), terms))))
+        .order((ts_rank(comments::search_vector, plainto_tsquery(english(), terms)).desc(),
+                comments::id.asc()))
+        .limit(pagination.get_per_page())
+        .offset(pagination.get_offset())
+        .load::<CommentHit>(conn)?
+        .into_iter()
+        .map(|hit| CommentHit { snippet: search::highlight(&hit.snippet), ..hit })
+        .collect::<Vec<_>>();
+
+    Ok(Json(json!({
+        "page": pagination.get_page(),
+        "per_page": pagination.get_per_page(),
+        "posts": {
+            "total": posts_total,
+            "results": post_hits,
+        },
+        "comments": {
+            "total": comments_total,
+            "results": comment_hits,
+        },
+    })))
+}
@@ -0,0 +1,49 @@
+use diesel::prelude::*;
+
+use rocket::State;
+use rocket_contrib::{Json, Value};
+
+use auth::{self, AuthError};
+use config::Config;
+use db::Db;
+use models::User;
+use schema::users::dsl::*;
+
+use endpoint_error::{EndpointError, EndpointResult};
+use endpoints::listing::lower;
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[post("/login", data = "<credentials>", format = "application/json")]
+fn create(db: State<Db>,
+          config: State<Config>,
+          credentials: Json<Credentials>)
+          -> EndpointResult<Json<Value>> {
+    let conn = &*db.pool().get()?;
+
+    let user = users.filter(lower(username).eq(credentials.username.to_lowercase()))
+        .filter(deleted_at.is_null())
+        .first::<User>(conn)
+        .optional()?;
+
+    let verified = match user {
+        Some(ref user) => auth::verify_password(user, &credentials.password),
+        None => auth::verify_dummy_password(&credentials.password),
+    };
+
+    let user = match user {
+        Some(ref user) if verified => user,
+        _ => return Err(EndpointError::Auth(AuthError::InvalidCredentials)),
+    };
+
+    let token = auth::issue_token(user, config.auth());
+
+    Ok(Json(json!({
+        "token": token.encode(&config.auth().secret),
+        "expires_at": token.expires_at,
+    })))
+}
@@ -0,0 +1,121 @@
+use diesel::prelude::*;
+use diesel;
+use diesel::expression::dsl::sql;
+use diesel::result::Error as DieselError;
+use diesel::types::BigInt;
+
+use rocket::{State, Response};
+use rocket::http::Status;
+use rocket_contrib::Json;
+
+use db::Db;
+use models::{NewTag, Post, Tag, TagCount};
+use roles::Capability;
+use sanitization::Sanitized;
+use schema::posts;
+use schema::tags;
+
+use auth::CurrentUser;
+use endpoint_error::EndpointResult;
+use tagging;
+use validation::Validate;
+use endpoints::authorization::{self, require};
+
+// Correlated count of the published posts carrying each tag.
+const PUBLISHED_POST_COUNT: &'static str = "(SELECT count(*) FROM post_tags \
+                                            JOIN posts ON posts.id = post_tags.post_id \
+                                            WHERE post_tags.tag_id = tags.id \
+                                            AND posts.published \
+                                            AND posts.deleted_at IS NULL)";
+
+/// Every tag with its number of published posts, for building a tag cloud.
+#[get("/tags", format = "application/json")]
+fn index(db: State<Db>) -> EndpointResult<Json<Vec<TagCount>>> {
+    let conn = &*db.pool().get()?;
+
+    let counts = tags::table.select((tags::id, tags::name, sql::<BigInt>(PUBLISHED_POST_COUNT)))
+        .order(tags::name.asc())
+        .load::<TagCount>(conn)?;
+
+    Ok(Json(counts))
+}
+
+#[post("/tags", data = "<new_tag>", format = "application/json")]
+fn create(db: State<Db>,
+          current_user: CurrentUser,
+          new_tag: Sanitized<NewTag>)
+          -> EndpointResult<Json<Tag>> {
+    let conn = &*db.pool().get()?;
+
+    require(&current_user.0, Capability::WritePosts)?;
+
+    let mut new_tag = new_tag.0;
+    new_tag.validate()?;
+    new_tag.name = tagging::normalize(&new_tag.name);
+
+    let tag = diesel::insert(&new_tag).into(tags::table)
+        .get_result::<Tag>(conn)?;
+
+    Ok(Json(tag))
+}
+
+#[get("/tags/<name>", format = "application/json")]
+fn show(name: String, db: State<Db>) -> EndpointResult<Json<Tag>> {
+    let conn = &*db.pool().get()?;
+
+    let tag = tagging::find_by_name(conn, &name)?;
+
+    Ok(Json(tag))
+}
+
+/// Renames a tag; every post carrying it follows.
+#[put("/tags/<name>", data = "<renamed_tag>", format = "application/json")]
+fn update(name: String,
+          db: State<Db>,
+          current_user: CurrentUser,
+          renamed_tag: Sanitized<NewTag>)
+          -> EndpointResult<Json<Tag>> {
+    let conn = &*db.pool().get()?;
+
+    require(&current_user.0, Capability::EditAnyPost)?;
+
+    let tag = tagging::find_by_name(conn, &name)?;
+    renamed_tag.validate()?;
+
+    let tag = diesel::update(tags::table.find(tag.id))
+        .set(tags::name.eq(tagging::normalize(&renamed_tag.name)))
+        .get_result::<Tag>(conn)?;
+
+    Ok(Json(tag))
+}
+
+#[delete("/tags/<name>", format = "application/json")]
+fn destroy(name: String, db: State<Db>, current_user: CurrentUser) -> EndpointResult<Response> {
+    let conn = &*db.pool().get()?;
+
+    require(&current_user.0, Capability::EditAnyPost)?;
+
+    let tag = tagging::find_by_name(conn, &name)?;
+    diesel::delete(tags::table.find(tag.id)).get_result::<Tag>(conn)?;
+
+    Response::build().status(Status::NoContent).ok()
+}
+
+#[get("/posts/<id>/tags", format = "application/json")]
+fn post_tags_index(id: i32,
+                   db: State<Db>,
+                   current_user: Option<CurrentUser>)
+                   -> EndpointResult<Json<Vec<Tag>>> {
+    let conn = &*db.pool().get()?;
+
+    let post = posts::table.find(id)
+        .filter(posts::deleted_at.is_null())
+        .first::<Post>(conn)?;
+    if !authorization::can_view(current_user.as_ref().map(|user| &user.0), &post) {
+        return Err(DieselError::NotFound.into());
+    }
+
+    let tags = tagging::tags_of_post(conn, post.id)?;
+
+    Ok(Json(tags))
+}
@@ -0,0 +1,214 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel;
+
+use rocket::State;
+use rocket_contrib::Json;
+
+use db::Db;
+use models::{Comment, Post, User};
+use schema::{comments, posts, users};
+use timestamps;
+
+use auth::CurrentUser;
+use endpoint_error::{EndpointError, EndpointResult};
+use endpoints::authorization::require_admin;
+use endpoints::listing::{ListQuery, Listing};
+use endpoints::pagination::Page;
+
+const SORTABLE_FIELDS: &'static [&'static str] = &["id", "deleted_at"];
+
+/// Deleted posts, until they get restored or purged. Admins only.
+#[get("/trash/posts", format = "application/json")]
+fn posts_index(db: State<Db>, current_user: CurrentUser) -> EndpointResult<Page<Post>> {
+    require_admin(&current_user.0)?;
+
+    let page = trashed_posts(&db, &Listing::default())?;
+
+    Ok(page)
+}
+
+#[get("/trash/posts?<query>", format = "application/json")]
+fn posts_index_paginated(db: State<Db>,
+                         current_user: CurrentUser,
+                         query: ListQuery)
+                         -> EndpointResult<Page<Post>> {
+    require_admin(&current_user.0)?;
+
+    let listing = query.parse(SORTABLE_FIELDS, &[])?;
+    let page = trashed_posts(&db, &listing)?;
+
+    Ok(page)
+}
+
+#[get("/trash/users", format = "application/json")]
+fn users_index(db: State<Db>, current_user: CurrentUser) -> EndpointResult<Page<User>> {
+    require_admin(&current_user.0)?;
+
+    let page = trashed_users(&db, &Listing::default())?;
+
+    Ok(page)
+}
+
+#[get("/trash/users?<query>", format = "application/json")]
+fn users_index_paginated(db: State<Db>,
+                         current_user: CurrentUser,
+                         query: ListQuery)
+                         -> EndpointResult<Page<User>> {
+    require_admin(&current_user.0)?;
+
+    let listing = query.parse(SORTABLE_FIELDS, &[])?;
+    let page = trashed_users(&db, &listing)?;
+
+    Ok(page)
+}
+
+#[get("/trash/comments", format = "application/json")]
+fn comments_index(db: State<Db>, current_user: CurrentUser) -> EndpointResult<Page<Comment>> {
+    require_admin(&current_user.0)?;
+
+    let page = trashed_comments(&db, &Listing::default())?;
+
+    Ok(page)
+}
+
+#[get("/trash/comments?<query>", format = "application/json")]
+fn comments_index_paginated(db: State<Db>,
+                            current_user: CurrentUser,
+                            query: ListQuery)
+                            -> EndpointResult<Page<Comment>> {
+    require_admin(&current_user.0)?;
+
+    let listing = query.parse(SORTABLE_FIELDS, &[])?;
+    let page = trashed_comments(&db, &listing)?;
+
+    Ok(page)
+}
+
+/// Takes a post out of the trash, along with the comments that were deleted
+/// with it (which share its deletion time). Only deleted posts can be
+/// restored, anything else is a `404 Not Found`.
+#[post("/posts/<id>/restore", format = "application/json")]
+fn restore_post(id: i32, db: State<Db>, current_user: CurrentUser) -> EndpointResult<Json<Post>> {
+    require_admin(&current_user.0)?;
+
+    let conn = &*db.pool().get()?;
+
+    let post = posts::table.find(id).filter(posts::deleted_at.is_not_null()).first::<Post>(conn)?;
+    let now = timestamps::now();
+
+    let post = conn.transaction(|| {
+        diesel::update(comments::table.filter(comments::post_id.eq(post.id))
+                .filter(comments::deleted_at.eq(post.deleted_at)))
+            .set((comments::deleted_at.eq(None::<NaiveDateTime>), comments::updated_at.eq(now)))
+            .execute(conn)?;
+
+        diesel::update(posts::table.find(post.id))
+            .set((posts::deleted_at.eq(None::<NaiveDateTime>), posts::updated_at.eq(now)))
+            .get_result::<Post>(conn)
+    })?;
+
+    Ok(Json(post))
+}
+
+/// Takes a user out of the trash, along with the posts and comments deleted
+/// with them (which share their deletion time): their own comments and the
+/// comments on their posts.
+#[post("/users/<id>/restore", format = "application/json")]
+fn restore_user(id: i32, db: State<Db>, current_user: CurrentUser) -> EndpointResult<Json<User>> {
+    require_admin(&current_user.0)?;
+
+    let conn = &*db.pool().get()?;
+
+    let user = users::table.find(id).filter(users::deleted_at.is_not_null()).first::<User>(conn)?;
+    let now = timestamps::now();
+
+    let user = conn.transaction(|| {
+        let post_ids = posts::table.select(posts::id)
+            .filter(posts::user_id.eq(user.id))
+            .filter(posts::deleted_at.eq(user.deleted_at));
+
+        diesel::update(comments::table.filter(comments::deleted_at.eq(user.deleted_at))
+                .filter(comments::user_id.eq(user.id).or(comments::post_id.eq_any(post_ids))))
+            .set((comments::deleted_at.eq(None::<NaiveDateTime>), comments::updated_at.eq(now)))
+            .execute(conn)?;
+
+        diesel::update(posts::table.filter(posts::user_id.eq(user.id))
+                .filter(posts::deleted_at.eq(user.deleted_at)))
+            .set((posts::deleted_at.eq(None::<NaiveDateTime>), posts::updated_at.eq(now)))
+            .execute(conn)?;
+
+        diesel::update(users::table.find(user.id))
+            .set((users::deleted_at.eq(None::<NaiveDateTime>), users::updated_at.eq(now)))
+            .get_result::<User>(conn)
+    })?;
+
+    Ok(Json(user))
+}
+
+/// Takes a comment out of the trash. A comment whose post is still deleted
+/// is a `409 Conflict`: the post has to be restored first.
+#[post("/comments/<id>/restore", format = "application/json")]
+fn restore_comment(id: i32,
+                   db: State<Db>,
+                   current_user: CurrentUser)
+                   -> EndpointResult<Json<Comment>> {
+    require_admin(&current_user.0)?;
+
+    let conn = &*db.pool().get()?;
+
+    let comment = comments::table.find(id)
+        .filter(comments::deleted_at.is_not_null())
+        .first::<Comment>(conn)?;
+    let post = posts::table.find(comment.post_id).first::<Post>(conn)?;
+    if post.deleted_at.is_some() {
+        return Err(EndpointError::Conflict(String::from("the comment's post is deleted, \
+                                                         restore the post first")));
+    }
+
+    let comment = diesel::update(comments::table.find(comment.id))
+        .set((comments::deleted_at.eq(None::<NaiveDateTime>),
+              comments::updated_at.eq(timestamps::now())))
+        .get_result::<Comment>(conn)?;
+
+    Ok(Json(comment))
+}
+
+fn trashed_posts(db: &Db, listing: &Listing) -> EndpointResult<Page<Post>> {
+    let conn = &*db.pool().get()?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         posts::id,
+                         listing.order("posts", "id"),
+                         posts::table.filter(posts::deleted_at.is_not_null()).into_boxed(),
+                         Post)?;
+
+    Ok(page)
+}
+
+fn trashed_users(db: &Db, listing: &Listing) -> EndpointResult<Page<User>> {
+    let conn = &*db.pool().get()?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         users::id,
+                         listing.order("users", "id"),
+                         users::table.filter(users::deleted_at.is_not_null()).into_boxed(),
+                         User)?;
+
+    Ok(page)
+}
+
+fn trashed_comments(db: &Db, listing: &Listing) -> EndpointResult<Page<Comment>> {
+    let conn = &*db.pool().get()?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         comments::id,
+                         listing.order("comments", "id"),
+                         comments::table.filter(comments::deleted_at.is_not_null()).into_boxed(),
+                         Comment)?;
+
+    Ok(page)
+}
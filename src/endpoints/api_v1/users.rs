@@ -1,85 +1,297 @@
 use diesel::prelude::*;
 use diesel;
+use diesel::expression::dsl::sql;
+use diesel::pg::{Pg, PgConnection};
+use diesel::query_builder::BoxedSelectStatement;
+use diesel::types::Integer;
 
 use rocket::{State, Response};
 use rocket::http::Status;
-use rocket_contrib::{JSON, Value};
+use rocket_contrib::Json;
 
-use db::{Db, DbError};
+use config::Config;
+use db::Db;
+use deletion;
 use models::User;
 use models::NewUser;
+use models::NewUserRecord;
 use models::UpdatedUser;
+use models::PasswordChange;
+use models::RoleAssignment;
+use roles::{Capability, Role};
+use sanitization::Sanitized;
 use schema::posts::dsl::*;
 use schema::users::dsl::*;
 use schema::users;
+use timestamps;
 
-use endpoint_error::EndpointResult;
+use auth::{self, AuthError, CurrentUser};
+use endpoint_error::{EndpointError, EndpointResult};
+use validation::Validate;
+use endpoints::authorization::{authorize, require};
 use endpoints::helpers::*;
-use endpoints::pagination::Pagination;
+use endpoints::listing::{self, ListQuery, Listing};
+use endpoints::pagination::Page;
+
+const SORTABLE_FIELDS: &'static [&'static str] = &["id", "username", "name", "role",
+                                                    "created_at", "updated_at"];
+const FILTERABLE_FIELDS: &'static [&'static str] = &["username", "role", "q"];
+
+/// Emails are private, only those who can manage users may look them up.
+const MANAGER_SORTABLE_FIELDS: &'static [&'static str] = &["id", "username", "name", "email",
+                                                            "role", "created_at", "updated_at"];
+const MANAGER_FILTERABLE_FIELDS: &'static [&'static str] = &["username", "email", "role", "q"];
+
+/// Locks the rows of the live admins until the end of the transaction.
+const LOCK_ADMINS: &'static str = "SELECT id FROM users \
+                                   WHERE role = 'admin' AND deleted_at IS NULL FOR UPDATE";
+
+type UsersQuery<'a> = BoxedSelectStatement<'a, users::SqlType, users::table, Pg>;
+
+/// Filters accepted by the user listing, `username` ignoring case and `q`
+/// matching the name or username. Deleted users are always left out.
+#[derive(Default)]
+struct UserFilter {
+    username: Option<String>,
+    email: Option<String>,
+    role: Option<String>,
+    q: Option<String>,
+}
+
+impl UserFilter {
+    fn parse(listing: &Listing) -> EndpointResult<UserFilter> {
+        let mut filter = UserFilter::default();
+
+        for &(ref field, ref value) in listing.filters() {
+            match field.as_str() {
+                "username" => filter.username = Some(value.clone()),
+                "email" => filter.email = Some(value.clone()),
+                "role" => {
+                    let value = listing::parse_value::<Role>(field, value)?;
+                    filter.role = Some(value.to_string());
+                }
+                "q" => filter.q = Some(value.clone()),
+                _ => {}
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn apply<'a>(&self, mut query: UsersQuery<'a>) -> UsersQuery<'a> {
+        query = query.filter(users::deleted_at.is_null());
+        if let Some(ref value) = self.username {
+            query = query.filter(listing::lower(users::username).eq(value.to_lowercase()));
+        }
+        if let Some(ref value) = self.email {
+            query = query.filter(users::email.eq(value.clone()));
+        }
+        if let Some(ref value) = self.role {
+            query = query.filter(users::role.eq(value.clone()));
+        }
+        if let Some(ref value) = self.q {
+            let pattern = listing::contains_pattern(value);
+            query = query.filter(listing::lower(users::name)
+                .like(pattern.clone())
+                .or(listing::lower(users::username).like(pattern)));
+        }
+
+        query
+    }
+}
 
 #[get("/users", format = "application/json")]
-fn index(db: State<Db>) -> EndpointResult<JSON<Value>> {
-    let results = all_users(&db, None)?;
+fn index(db: State<Db>) -> EndpointResult<Page<User>> {
+    let page = all_users(&db, &Listing::default())?;
 
-    Ok(JSON(json!(results)))
+    Ok(page)
 }
 
-#[get("/users?<pagination>", format = "application/json")]
-fn index_paginated(db: State<Db>, pagination: Pagination) -> EndpointResult<JSON<Value>> {
-    let results = all_users(&db, Some(pagination))?;
+#[get("/users?<query>", format = "application/json")]
+fn index_paginated(db: State<Db>,
+                   query: ListQuery,
+                   current_user: Option<CurrentUser>)
+                   -> EndpointResult<Page<User>> {
+    let manager = current_user.map_or(false, |user| user.0.can(Capability::ManageUsers));
+    let listing = if manager {
+        query.parse(MANAGER_SORTABLE_FIELDS, MANAGER_FILTERABLE_FIELDS)?
+    } else {
+        query.parse(SORTABLE_FIELDS, FILTERABLE_FIELDS)?
+    };
+    let page = all_users(&db, &listing)?;
 
-    Ok(JSON(json!(results)))
+    Ok(page)
 }
 
 #[post("/users", data = "<new_user>", format = "application/json")]
-fn create(db: State<Db>, new_user: JSON<NewUser>) -> EndpointResult<JSON<User>> {
+fn create(db: State<Db>, new_user: Sanitized<NewUser>) -> EndpointResult<Json<User>> {
     let conn = &*db.pool().get()?;
 
-    let user = diesel::insert(&new_user.0).into(users::table)
+    let new_user = new_user.0;
+    new_user.validate()?;
+
+    let record = NewUserRecord {
+        name: &new_user.name,
+        username: &new_user.username,
+        email: &new_user.email,
+        password_hash: auth::hash_password(&new_user.password)?,
+    };
+
+    let user = diesel::insert(&record).into(users::table)
         .get_result::<User>(conn)?;
 
-    Ok(JSON(user))
+    Ok(Json(user))
 }
 
 #[get("/users/<id>", format = "application/json")]
-fn show(id: i32, db: State<Db>) -> EndpointResult<JSON<User>> {
+fn show(id: i32, db: State<Db>) -> EndpointResult<Json<User>> {
     let conn = &*db.pool().get()?;
 
-    let user = users.find(id).first::<User>(conn)?;
+    let user = users.find(id).filter(users::deleted_at.is_null()).first::<User>(conn)?;
 
-    Ok(JSON(user))
+    Ok(Json(user))
 }
 
 #[put("/users/<id>", data = "<updated_user>", format = "application/json")]
-fn update(db: State<Db>, id: i32, updated_user: JSON<UpdatedUser>) -> EndpointResult<JSON<User>> {
+fn update(db: State<Db>,
+          current_user: CurrentUser,
+          id: i32,
+          updated_user: Sanitized<UpdatedUser>)
+          -> EndpointResult<Json<User>> {
     let conn = &*db.pool().get()?;
 
-    let user = diesel::update(users.find(id)).set(&updated_user.0)
+    let user = users.find(id).filter(users::deleted_at.is_null()).first::<User>(conn)?;
+    authorize(&current_user.0, &user)?;
+    updated_user.validate()?;
+
+    let user = diesel::update(users.find(id))
+        .set((&updated_user.0, users::updated_at.eq(timestamps::now())))
         .get_result::<User>(conn)?;
 
-    Ok(JSON(user))
+    Ok(Json(user))
 }
 
+/// Deletes the user and deals with their posts and comments following the
+/// configured policies, all in one transaction.
 #[delete("/users/<id>", format = "application/json")]
-fn destroy(id: i32, db: State<Db>) -> EndpointResult<Response> {
+fn destroy(id: i32,
+           db: State<Db>,
+           config: State<Config>,
+           current_user: CurrentUser)
+           -> EndpointResult<Response> {
+    let conn = &*db.pool().get()?;
+
+    let user = users.find(id).filter(users::deleted_at.is_null()).first::<User>(conn)?;
+    authorize(&current_user.0, &user)?;
+
+    conn.transaction(|| -> EndpointResult<()> {
+        ensure_not_last_admin(conn, &user, "deleted")?;
+        deletion::delete_user(conn, &user, config.deletion())?;
+
+        Ok(())
+    })?;
+
+    Response::build().status(Status::NoContent).ok()
+}
+
+#[put("/users/<id>/password", data = "<password_change>", format = "application/json")]
+fn change_password(db: State<Db>,
+                   current_user: CurrentUser,
+                   id: i32,
+                   password_change: Json<PasswordChange>)
+                   -> EndpointResult<Response> {
+    let current_user = current_user.0;
+
+    if current_user.id != id ||
+       !auth::verify_password(&current_user, &password_change.old_password) {
+        return Err(AuthError::InvalidCredentials.into());
+    }
+    password_change.validate()?;
+
     let conn = &*db.pool().get()?;
+    let new_hash = auth::hash_password(&password_change.new_password)?;
 
-    diesel::delete(users.find(id)).get_result::<User>(conn)?;
+    diesel::update(users.find(id))
+        .set((password_hash.eq(new_hash), users::updated_at.eq(timestamps::now())))
+        .get_result::<User>(conn)?;
 
     Response::build().status(Status::NoContent).ok()
 }
 
-fn all_users(db: &Db, pagination: Option<Pagination>) -> Result<Vec<User>, DbError> {
-    let mut query = users::table.into_boxed();
+#[put("/users/<id>/role", data = "<assignment>", format = "application/json")]
+fn grant_role(db: State<Db>,
+              current_user: CurrentUser,
+              id: i32,
+              assignment: Json<RoleAssignment>)
+              -> EndpointResult<Json<User>> {
+    require(&current_user.0, Capability::ManageUsers)?;
+
+    let new_role = assignment.role
+        .parse::<Role>()
+        .map_err(|err| EndpointError::BadRequest(err.to_string()))?;
+
+    let conn = &*db.pool().get()?;
+
+    let user = conn.transaction(|| set_role(conn, id, new_role))?;
+
+    Ok(Json(user))
+}
+
+#[delete("/users/<id>/role", format = "application/json")]
+fn revoke_role(db: State<Db>, current_user: CurrentUser, id: i32) -> EndpointResult<Json<User>> {
+    require(&current_user.0, Capability::ManageUsers)?;
+
+    let conn = &*db.pool().get()?;
+
+    let user = conn.transaction(|| set_role(conn, id, Role::default()))?;
+
+    Ok(Json(user))
+}
 
-    if let Some(pagination) = pagination {
-        let page = pagination.get_page();
-        let per_page = pagination.get_per_page();
-        query = query.limit(per_page).offset(per_page * (page - 1));
+/// Gives user `id` the `new_role`, refusing with a conflict to demote the
+/// last remaining admin.
+fn set_role(conn: &PgConnection, id: i32, new_role: Role) -> EndpointResult<User> {
+    let user = users.find(id).filter(users::deleted_at.is_null()).first::<User>(conn)?;
+
+    if new_role != Role::Admin {
+        ensure_not_last_admin(conn, &user, "demoted")?;
+    }
+
+    let user = diesel::update(users.find(id))
+        .set((role.eq(new_role.to_string()), users::updated_at.eq(timestamps::now())))
+        .get_result::<User>(conn)?;
+
+    Ok(user)
+}
+
+/// Refuses with a conflict to take away the last remaining admin, which would
+/// leave nobody able to manage users. Must run inside a transaction: the admin
+/// rows stay locked until it ends, so two admins demoting or deleting each
+/// other at the same time cannot both succeed.
+fn ensure_not_last_admin(conn: &PgConnection, user: &User, action: &str) -> EndpointResult<()> {
+    if user.role() != Role::Admin {
+        return Ok(());
     }
 
+    let admins = sql::<Integer>(LOCK_ADMINS).load::<i32>(conn)?;
+
+    if admins.len() <= 1 {
+        return Err(EndpointError::Conflict(format!("the last admin cannot be {}", action)));
+    }
+
+    Ok(())
+}
+
+fn all_users(db: &Db, listing: &Listing) -> EndpointResult<Page<User>> {
     let conn = &*db.pool().get()?;
+    let filter = UserFilter::parse(listing)?;
+
+    let page = paginate!(conn,
+                         listing.pagination(),
+                         users::id,
+                         listing.order("users", "id"),
+                         filter.apply(users::table.into_boxed()),
+                         User)?;
 
-    query.load::<User>(conn)
-        .map_err(|err| DbError::from(err))
+    Ok(page)
 }
@@ -0,0 +1,118 @@
+use models::{Comment, Post, User};
+use models::{UpdatedComment, UpdatedPost};
+use roles::{Capability, Role};
+
+use endpoint_error::{EndpointError, EndpointResult};
+
+/// A resource that belongs to a user. Only that user, or someone holding the
+/// resource's managing capability, may modify it.
+pub trait Owned {
+    fn owner_id(&self) -> Option<i32>;
+
+    fn managing_capability() -> Capability;
+}
+
+/// A changeset carrying fields that would move a resource to another owner.
+pub trait OwnershipChangeset {
+    fn strip_ownership(&mut self);
+}
+
+impl Owned for Post {
+    fn owner_id(&self) -> Option<i32> {
+        self.user_id
+    }
+
+    fn managing_capability() -> Capability {
+        Capability::EditAnyPost
+    }
+}
+
+impl Owned for Comment {
+    fn owner_id(&self) -> Option<i32> {
+        Some(self.user_id)
+    }
+
+    fn managing_capability() -> Capability {
+        Capability::ModerateComments
+    }
+}
+
+impl Owned for User {
+    fn owner_id(&self) -> Option<i32> {
+        Some(self.id)
+    }
+
+    fn managing_capability() -> Capability {
+        Capability::ManageUsers
+    }
+}
+
+impl OwnershipChangeset for UpdatedPost {
+    fn strip_ownership(&mut self) {
+        self.user_id = None;
+    }
+}
+
+impl OwnershipChangeset for UpdatedComment {
+    fn strip_ownership(&mut self) {
+        self.user_id = None;
+        self.post_id = None;
+    }
+}
+
+pub fn is_admin(user: &User) -> bool {
+    user.role() == Role::Admin
+}
+
+/// Fails with `EndpointError::Forbidden` unless `user` is an admin.
+pub fn require_admin(user: &User) -> EndpointResult<()> {
+    if is_admin(user) {
+        Ok(())
+    } else {
+        Err(EndpointError::Forbidden)
+    }
+}
+
+/// Fails with `EndpointError::Forbidden` unless `user` has `capability`.
+pub fn require(user: &User, capability: Capability) -> EndpointResult<()> {
+    if user.can(capability) {
+        Ok(())
+    } else {
+        Err(EndpointError::Forbidden)
+    }
+}
+
+/// Fails with `EndpointError::Forbidden` unless `user` owns `resource` or has
+/// the capability to manage resources of its kind.
+pub fn authorize<T: Owned>(user: &User, resource: &T) -> EndpointResult<()> {
+    if resource.owner_id() == Some(user.id) {
+        Ok(())
+    } else {
+        require(user, T::managing_capability())
+    }
+}
+
+/// Whether `viewer` may see `post`. Published posts are public, drafts only
+/// show up for their author and whoever may edit any post.
+pub fn can_view(viewer: Option<&User>, post: &Post) -> bool {
+    post.published ||
+    viewer.map_or(false, |viewer| {
+        post.user_id == Some(viewer.id) || viewer.can(Capability::EditAnyPost)
+    })
+}
+
+/// Whether `viewer` may see `comment`. Only approved comments are public, the
+/// rest show up for their author and for moderators.
+pub fn can_view_comment(viewer: Option<&User>, comment: &Comment) -> bool {
+    comment.published ||
+    viewer.map_or(false, |viewer| {
+        comment.user_id == viewer.id || viewer.can(Capability::ModerateComments)
+    })
+}
+
+/// Drops the ownership fields from `changeset` unless `user` is an admin.
+pub fn restrict_changeset<T: OwnershipChangeset>(user: &User, changeset: &mut T) {
+    if !is_admin(user) {
+        changeset.strip_ownership();
+    }
+}
@@ -0,0 +1,45 @@
+use rocket::{Request, Response};
+use rocket::http::Status;
+use rocket_contrib::Value;
+
+use endpoints::helpers::*;
+
+#[error(400)]
+fn bad_request(request: &Request) -> Response<'static> {
+    bad_request_json_response(request,
+                              "malformed_request",
+                              "the request could not be understood, check the JSON body")
+}
+
+#[error(401)]
+fn unauthorized(request: &Request) -> Response<'static> {
+    unauthorized_json_response(request)
+}
+
+#[error(403)]
+fn forbidden(request: &Request) -> Response<'static> {
+    forbidden_json_response(request)
+}
+
+#[error(404)]
+fn not_found(request: &Request) -> Response<'static> {
+    error_json_response(request,
+                        Status::NotFound,
+                        "route_not_found",
+                        "no route matches the requested path",
+                        Value::Null)
+}
+
+#[error(422)]
+fn unprocessable_entity(request: &Request) -> Response<'static> {
+    error_json_response(request,
+                        Status::UnprocessableEntity,
+                        "unprocessable_entity",
+                        "the request was well-formed but could not be processed",
+                        Value::Null)
+}
+
+#[error(500)]
+fn internal_error(request: &Request) -> Response<'static> {
+    ise_json_response(request)
+}
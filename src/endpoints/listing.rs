@@ -0,0 +1,214 @@
+use std::str::FromStr;
+
+use diesel::expression::dsl::sql;
+use diesel::expression::SqlLiteral;
+use diesel::types::{Bool, VarChar};
+use rocket::request::{FormItems, FromForm};
+
+use endpoint_error::{EndpointError, EndpointResult};
+use endpoints::pagination::Pagination;
+
+sql_function!(lower, lower_t, (x: VarChar) -> VarChar);
+
+const SORT_PARAM: &'static str = "sort";
+const PAGINATION_PARAMS: &'static [&'static str] = &["page", "per_page", "after", "limit"];
+
+/// The raw query string of a listing endpoint.
+///
+/// Unlike a derived `FromForm` this accepts any parameter, so that unknown
+/// ones can be reported as a 400 by `parse` instead of making the route
+/// forward to a 404.
+pub struct ListQuery {
+    params: Vec<(String, String)>,
+}
+
+impl<'f> FromForm<'f> for ListQuery {
+    type Error = ();
+
+    fn from_form(items: &mut FormItems<'f>, _strict: bool) -> Result<ListQuery, ()> {
+        let mut params = Vec::new();
+
+        for (key, value) in items {
+            let key = key.url_decode().map_err(|_| ())?;
+            let value = value.url_decode().map_err(|_| ())?;
+            params.push((key, value));
+        }
+
+        Ok(ListQuery { params: params })
+    }
+}
+
+impl ListQuery {
+    /// Removes `name` from the query, for endpoint specific parameters that
+    /// are neither filters nor paging. Returns its last value.
+    pub fn take(&mut self, name: &str) -> Option<String> {
+        let mut value = None;
+
+        self.params.retain(|&(ref key, ref param)| if key == name {
+            value = Some(param.clone());
+            false
+        } else {
+            true
+        });
+
+        value
+    }
+
+    /// Validates the query against the fields a resource allows sorting and
+    /// filtering on (which must be columns of its table in `schema.rs`).
+    pub fn parse(&self, sortable: &[&str], filterable: &[&str]) -> EndpointResult<Listing> {
+        let (mut page, mut per_page, mut after, mut limit) = (None, None, None, None);
+        let mut sort = Vec::new();
+        let mut filters = Vec::new();
+
+        for &(ref key, ref value) in &self.params {
+            match key.as_str() {
+                "page" => page = Some(parse_value(key, value)?),
+                "per_page" => per_page = Some(parse_value(key, value)?),
+                "after" => after = Some(value.clone()),
+                "limit" => limit = Some(parse_value(key, value)?),
+                SORT_PARAM => sort = parse_sort(value, sortable)?,
+                key if filterable.contains(&key) => filters.push((key.to_owned(), value.clone())),
+                key => {
+                    return Err(bad_request(format!("unknown parameter '{}', expected one of: {}",
+                                                   key,
+                                                   allowed_params(filterable))))
+                }
+            }
+        }
+
+        let pagination = Pagination::new(page, per_page, after, limit);
+        pagination.validate()?;
+
+        if pagination.is_keyset() && !sort.is_empty() {
+            return Err(bad_request(String::from("'sort' cannot be combined with cursor \
+                                                 pagination")));
+        }
+
+        Ok(Listing {
+            pagination: pagination,
+            sort: sort,
+            filters: filters,
+        })
+    }
+}
+
+fn allowed_params(filterable: &[&str]) -> String {
+    let mut params = filterable.to_vec();
+    params.push(SORT_PARAM);
+    params.extend_from_slice(PAGINATION_PARAMS);
+    params.join(", ")
+}
+
+fn parse_sort(value: &str, sortable: &[&str]) -> EndpointResult<Vec<(String, bool)>> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            let (name, descending) = if field.starts_with('-') {
+                (&field[1..], true)
+            } else {
+                (field, false)
+            };
+
+            if sortable.contains(&name) {
+                Ok((name.to_owned(), descending))
+            } else {
+                Err(bad_request(format!("cannot sort by '{}', expected one of: {}",
+                                        name,
+                                        sortable.join(", "))))
+            }
+        })
+        .collect()
+}
+
+fn bad_request(message: String) -> EndpointError {
+    EndpointError::BadRequest(message)
+}
+
+/// Parses a filter or paging value, answering 400 when it has the wrong type.
+pub fn parse_value<T: FromStr>(field: &str, value: &str) -> EndpointResult<T> {
+    value.parse()
+        .map_err(|_| bad_request(format!("'{}' has an invalid value '{}'", field, value)))
+}
+
+/// A `LIKE` pattern matching `value` anywhere, case-insensitively when
+/// compared against `lower(column)`.
+pub fn contains_pattern(value: &str) -> String {
+    let escaped = value.to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+/// A validated listing request: paging, sort order and raw filters.
+pub struct Listing {
+    pagination: Pagination,
+    sort: Vec<(String, bool)>,
+    filters: Vec<(String, String)>,
+}
+
+/// What the routes without a query string list, top-level and nested alike:
+/// the first page of the default size, unsorted and unfiltered.
+impl Default for Listing {
+    fn default() -> Listing {
+        Listing {
+            pagination: Pagination::default(),
+            sort: Vec::new(),
+            filters: Vec::new(),
+        }
+    }
+}
+
+impl Listing {
+    /// The requested page, the first one of the default size if none was.
+    pub fn pagination(&self) -> &Pagination {
+        &self.pagination
+    }
+
+    pub fn filters(&self) -> &[(String, String)] {
+        &self.filters
+    }
+
+    /// The `ORDER BY` clause for `table`, always ending on `key` so pages are
+    /// stable. Only whitelisted column names ever get here.
+    pub fn order(&self, table: &str, key: &str) -> SqlLiteral<Bool> {
+        let mut clauses = self.sort
+            .iter()
+            .map(|&(ref field, descending)| {
+                format!("{}.{} {}", table, field, if descending { "DESC" } else { "ASC" })
+            })
+            .collect::<Vec<_>>();
+
+        if !self.sort.iter().any(|&(ref field, _)| field == key) {
+            clauses.push(format!("{}.{} ASC", table, key));
+        }
+
+        sql::<Bool>(&clauses.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_listing_is_the_first_page_of_the_default_size() {
+        let listing = Listing::default();
+
+        assert!(!listing.pagination().is_keyset());
+        assert_eq!(listing.pagination().get_page(), 1);
+        assert_eq!(listing.pagination().get_per_page(), 10);
+    }
+
+    #[test]
+    fn query_without_paging_params_gets_the_default_size() {
+        let query = ListQuery { params: vec![(String::from("sort"), String::from("-id"))] };
+        let listing = query.parse(&["id"], &[]).unwrap();
+
+        assert_eq!(listing.pagination().get_page(), 1);
+        assert_eq!(listing.pagination().get_per_page(), 10);
+    }
+}
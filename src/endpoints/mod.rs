@@ -1,30 +1,118 @@
 
-pub mod api_v1;
+#[macro_use]
 pub mod pagination;
+pub mod listing;
+pub mod api_v1;
+pub mod catchers;
+pub mod authorization;
+pub mod request_id;
 
 pub mod helpers {
     use rocket::http::Status;
-    use rocket::Response;
-    use rocket_contrib::{JSON, Value};
+    use rocket::{Request, Response};
+    use rocket_contrib::{Json, Value};
     use std::io::Cursor;
 
+    use endpoints::request_id::request_id;
+
     pub fn empty_response_with_status<'r>(status: Status) -> Response<'r> {
         Response::build().status(status).finalize()
     }
 
     pub fn json_response_with_status<'r>(status: Status, json: Value) -> Response<'r> {
         let mut response = empty_response_with_status(status);
-        response.set_sized_body(Cursor::new(JSON(json).to_string()));
+        response.set_sized_body(Cursor::new(Json(json).to_string()));
         response
     }
 
-    pub fn not_found_json_response<'r>() -> Response<'r> {
-        json_response_with_status(Status::NotFound, json!({"status": "not_found"}))
+    /// Builds the error envelope shared by every error response.
+    pub fn error_json_response<'r>(request: &Request,
+                                   status: Status,
+                                   code: &str,
+                                   message: &str,
+                                   details: Value)
+                                   -> Response<'r> {
+        json_response_with_status(status,
+                                  json!({
+                                      "code": code,
+                                      "message": message,
+                                      "details": details,
+                                      "request_id": request_id(request),
+                                  }))
+    }
+
+    pub fn not_found_json_response<'r>(request: &Request) -> Response<'r> {
+        error_json_response(request,
+                            Status::NotFound,
+                            "not_found",
+                            "the requested resource does not exist",
+                            Value::Null)
+    }
+
+    pub fn bad_request_json_response<'r>(request: &Request,
+                                         code: &str,
+                                         message: &str)
+                                         -> Response<'r> {
+        error_json_response(request, Status::BadRequest, code, message, Value::Null)
+    }
+
+    pub fn unauthorized_json_response<'r>(request: &Request) -> Response<'r> {
+        error_json_response(request,
+                            Status::Unauthorized,
+                            "unauthorized",
+                            "a valid bearer token is required",
+                            Value::Null)
+    }
+
+    pub fn forbidden_json_response<'r>(request: &Request) -> Response<'r> {
+        error_json_response(request,
+                            Status::Forbidden,
+                            "forbidden",
+                            "you are not allowed to perform this action",
+                            Value::Null)
+    }
+
+    pub fn conflict_json_response<'r>(request: &Request,
+                                      code: &str,
+                                      field: Option<String>,
+                                      message: &str)
+                                      -> Response<'r> {
+        error_json_response(request,
+                            Status::Conflict,
+                            code,
+                            message,
+                            json!({"field": field}))
+    }
+
+    pub fn unprocessable_entity_json_response<'r>(request: &Request,
+                                                  code: &str,
+                                                  errors: Value)
+                                                  -> Response<'r> {
+        error_json_response(request,
+                            Status::UnprocessableEntity,
+                            code,
+                            "the request body is invalid",
+                            errors)
+    }
+
+    pub fn service_unavailable_json_response<'r>(request: &Request,
+                                                 retry_after: u32)
+                                                 -> Response<'r> {
+        let mut response = error_json_response(request,
+                                               Status::ServiceUnavailable,
+                                               "service_unavailable",
+                                               "the service is busy, try again later",
+                                               Value::Null);
+        response.set_raw_header("Retry-After", retry_after.to_string());
+        response
     }
 
-    pub fn ise_json_response<'r>() -> Response<'r> {
-        json_response_with_status(Status::InternalServerError,
-                                  json!({"status": "an internal error has occured"}))
+    pub fn ise_json_response<'r>(request: &Request) -> Response<'r> {
+        error_json_response(request,
+                            Status::InternalServerError,
+                            "internal_error",
+                            "an internal error has occured",
+                            Value::Null)
     }
 
     pub fn ok_json_response<'r>(json: Value) -> Response<'r> {
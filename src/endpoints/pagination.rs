@@ -1,24 +1,90 @@
 use std::default::Default;
+use std::str;
+
+use base64;
+use rocket::Request;
+use rocket::response::{self, Responder};
+use serde::Serialize;
+
+use models::{Comment, Post, User};
+
+use endpoint_error::{EndpointError, EndpointResult};
+use endpoints::helpers::*;
 
 const DEFAULT_PER_PAGE: i64 = 10;
 const DEFAULT_PAGE: i64 = 1;
+const MAX_PER_PAGE: i64 = 100;
+const CURSOR_PREFIX: &'static str = "id:";
+
+/// Loads `$query` as a `Page<$model>` according to `$pagination`.
+///
+/// There is always a page, listings without paging parameters get the default
+/// size. `$query` is bound once, as a closure building a fresh query, since the
+/// offset mode runs it twice (the count and the page). `$key` is the integer
+/// primary key column used for keyset (cursor) pagination and `$order` the
+/// ordering used otherwise.
+macro_rules! paginate {
+    ($conn:expr, $pagination:expr, $key:expr, $order:expr, $query:expr, $model:ty) => {{
+        use endpoints::pagination::{Page, Pagination};
 
-#[derive(FromForm)]
+        let conn = $conn;
+        let pagination: &Pagination = $pagination;
+        let query = || $query;
+
+        if pagination.is_keyset() {
+            let limit = pagination.get_limit();
+
+            query().filter($key.gt(pagination.get_after().unwrap_or(0)))
+                .order($key.asc())
+                .limit(limit + 1)
+                .load::<$model>(conn)
+                .map(|items| Page::keyset(items, limit))
+        } else {
+            query().count().get_result::<i64>(conn).and_then(|total| {
+                query().order($order)
+                    .limit(pagination.get_per_page())
+                    .offset(pagination.get_offset())
+                    .load::<$model>(conn)
+                    .map(|items| Page::new(items, total, pagination))
+            })
+        }
+    }}
+}
+
+/// Listing parameters. Either offset based (`?page=N&per_page=M`) or keyset
+/// based (`?after=<cursor>&limit=N`), the latter being what large tables want.
 pub struct Pagination {
     per_page: Option<i64>,
-    page: Option<i64>
+    page: Option<i64>,
+    after: Option<String>,
+    limit: Option<i64>,
 }
 
 impl Default for Pagination {
     fn default() -> Self {
         Self {
             per_page: Some(DEFAULT_PER_PAGE),
-            page: Some(DEFAULT_PAGE)
+            page: Some(DEFAULT_PAGE),
+            after: None,
+            limit: None,
         }
     }
 }
 
 impl Pagination {
+    pub fn new(page: Option<i64>,
+               per_page: Option<i64>,
+               after: Option<String>,
+               limit: Option<i64>)
+               -> Pagination {
+        Pagination {
+            per_page: per_page,
+            page: page,
+            after: after,
+            limit: limit,
+        }
+    }
+
     pub fn get_per_page(&self) -> i64 {
         self.per_page.unwrap_or(DEFAULT_PER_PAGE)
     }
@@ -26,4 +92,286 @@ impl Pagination {
     pub fn get_page(&self) -> i64 {
         self.page.unwrap_or(DEFAULT_PAGE)
     }
+
+    /// Saturates instead of overflowing; `validate` rejects pages that far.
+    pub fn get_offset(&self) -> i64 {
+        self.checked_offset().unwrap_or_else(i64::max_value)
+    }
+
+    fn checked_offset(&self) -> Option<i64> {
+        self.get_page().checked_sub(1).and_then(|skipped| self.get_per_page().checked_mul(skipped))
+    }
+
+    pub fn is_keyset(&self) -> bool {
+        self.after.is_some() || self.limit.is_some()
+    }
+
+    pub fn get_limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_PER_PAGE)
+    }
+
+    /// The key the requested page starts after, if a valid cursor was given.
+    pub fn get_after(&self) -> Option<i32> {
+        self.after.as_ref().and_then(|cursor| decode_cursor(cursor))
+    }
+
+    /// Rejects out of range values, mixed modes and cursors we did not issue.
+    pub fn validate(&self) -> EndpointResult<()> {
+        if self.is_keyset() && (self.page.is_some() || self.per_page.is_some()) {
+            return Err(bad_request("'after'/'limit' cannot be combined with 'page'/'per_page'"));
+        }
+
+        if self.get_page() < 1 {
+            return Err(bad_request("'page' must be greater than 0"));
+        }
+
+        for &(param, value) in &[("per_page", self.get_per_page()), ("limit", self.get_limit())] {
+            if value < 1 || value > MAX_PER_PAGE {
+                return Err(bad_request(&format!("'{}' must be between 1 and {}",
+                                                param,
+                                                MAX_PER_PAGE)));
+            }
+        }
+
+        if self.checked_offset().is_none() {
+            return Err(bad_request("'page' is too large"));
+        }
+
+        if self.after.is_some() && self.get_after().is_none() {
+            return Err(bad_request("'after' is not a valid cursor"));
+        }
+
+        Ok(())
+    }
+}
+
+fn bad_request(message: &str) -> EndpointError {
+    EndpointError::BadRequest(message.to_owned())
+}
+
+pub fn encode_cursor(key: i32) -> String {
+    base64::encode_config(format!("{}{}", CURSOR_PREFIX, key).as_bytes(), base64::URL_SAFE)
+}
+
+pub fn decode_cursor(cursor: &str) -> Option<i32> {
+    let bytes = match base64::decode_config(cursor, base64::URL_SAFE) {
+        Ok(bytes) => bytes,
+        Err(_) => return None,
+    };
+
+    match str::from_utf8(&bytes) {
+        Ok(decoded) if decoded.starts_with(CURSOR_PREFIX) => {
+            decoded[CURSOR_PREFIX.len()..].parse().ok()
+        }
+        _ => None,
+    }
+}
+
+/// Rows that can be paginated by keyset, i.e. that have an increasing key.
+pub trait Keyed {
+    fn key(&self) -> i32;
+}
+
+impl Keyed for Post {
+    fn key(&self) -> i32 {
+        self.id
+    }
+}
+
+impl Keyed for User {
+    fn key(&self) -> i32 {
+        self.id
+    }
+}
+
+impl Keyed for Comment {
+    fn key(&self) -> i32 {
+        self.id
+    }
+}
+
+enum PageMeta {
+    Offset { total: i64, page: i64, per_page: i64 },
+    Keyset { limit: i64, next: Option<String> },
+}
+
+/// One page of a listing plus what a client needs to walk the rest of it.
+///
+/// The items are sent as a bare JSON array; the metadata travels in headers so
+/// existing clients keep working. Offset pages carry `X-Total-Count`, `X-Page`,
+/// `X-Per-Page` and `X-Total-Pages`, keyset pages carry `X-Next-Cursor`, and
+/// both get an RFC 5988 `Link` header.
+pub struct Page<T> {
+    items: Vec<T>,
+    meta: PageMeta,
+}
+
+impl<T: Serialize> Page<T> {
+    pub fn new(items: Vec<T>, total: i64, pagination: &Pagination) -> Page<T> {
+        Page {
+            items: items,
+            meta: PageMeta::Offset {
+                total: total,
+                page: pagination.get_page(),
+                per_page: pagination.get_per_page(),
+            },
+        }
+    }
+
+    /// Builds a keyset page out of up to `limit + 1` rows; the extra row only
+    /// tells us there is a next page.
+    pub fn keyset(mut items: Vec<T>, limit: i64) -> Page<T>
+        where T: Keyed
+    {
+        let has_more = items.len() as i64 > limit;
+        items.truncate(limit as usize);
+
+        let next = if has_more {
+            items.last().map(|item| encode_cursor(item.key()))
+        } else {
+            None
+        };
+
+        Page {
+            items: items,
+            meta: PageMeta::Keyset {
+                limit: limit,
+                next: next,
+            },
+        }
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Transforms the items of the page, keeping its metadata.
+    pub fn map<U, F>(self, f: F) -> Page<U>
+        where U: Serialize,
+              F: FnOnce(Vec<T>) -> Vec<U>
+    {
+        Page {
+            items: f(self.items),
+            meta: self.meta,
+        }
+    }
+
+    fn total_pages(total: i64, per_page: i64) -> i64 {
+        if per_page <= 0 {
+            return 0;
+        }
+
+        (total + per_page - 1) / per_page
+    }
+}
+
+/// Renders `<path?params>; rel="..."` entries, keeping every query parameter
+/// (filters, sorting...) except the ones in `replaced`.
+fn link_header(path: &str,
+               query: Option<&str>,
+               replaced: &[&str],
+               links: &[(String, &str)])
+               -> String {
+    let params = query.map(|query| {
+            query.split('&')
+                .filter(|param| {
+                    !param.is_empty() &&
+                    !replaced.iter().any(|name| param.starts_with(&format!("{}=", name)))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    links.iter()
+        .map(|&(ref page_params, rel)| {
+            let mut query = params.clone();
+            query.push(page_params);
+            format!("<{}?{}>; rel=\"{}\"", path, query.join("&"), rel)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl<'r, T: Serialize> Responder<'r> for Page<T> {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let mut response = ok_json_response(json!(self.items));
+        let path = request.uri().path();
+        let query = request.uri().query();
+
+        match self.meta {
+            PageMeta::Offset { total, page, per_page } => {
+                let total_pages = Page::<T>::total_pages(total, per_page);
+
+                response.set_raw_header("X-Total-Count", total.to_string());
+                response.set_raw_header("X-Page", page.to_string());
+                response.set_raw_header("X-Per-Page", per_page.to_string());
+                response.set_raw_header("X-Total-Pages", total_pages.to_string());
+
+                if total_pages > 1 {
+                    let link = |page: i64, rel| {
+                        (format!("page={}&per_page={}", page, per_page), rel)
+                    };
+
+                    let mut links = vec![link(1, "first")];
+                    if page > 1 {
+                        links.push(link(page - 1, "prev"));
+                    }
+                    if page < total_pages {
+                        links.push(link(page + 1, "next"));
+                    }
+                    links.push(link(total_pages, "last"));
+
+                    let link = link_header(path, query, &["page", "per_page"], &links);
+                    response.set_raw_header("Link", link);
+                }
+            }
+            PageMeta::Keyset { limit, next } => {
+                if let Some(next) = next {
+                    let links = [(format!("after={}&limit={}", next, limit), "next")];
+
+                    let link = link_header(path, query, &["after", "limit"], &links);
+                    response.set_raw_header("Link", link);
+                    response.set_raw_header("X-Next-Cursor", next);
+                }
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        for key in &[0, 1, 42, i32::max_value()] {
+            assert_eq!(decode_cursor(&encode_cursor(*key)), Some(*key));
+        }
+    }
+
+    #[test]
+    fn rejects_pages_past_the_largest_offset() {
+        let pagination = |page| Pagination::new(Some(page), Some(MAX_PER_PAGE), None, None);
+
+        assert!(pagination(i64::max_value()).validate().is_err());
+        assert_eq!(pagination(i64::max_value()).get_offset(), i64::max_value());
+        assert!(pagination(3).validate().is_ok());
+        assert_eq!(pagination(3).get_offset(), 2 * MAX_PER_PAGE);
+    }
+
+    #[test]
+    fn rejects_bad_cursors() {
+        let encode = |text: &str| base64::encode_config(text.as_bytes(), base64::URL_SAFE);
+
+        assert_eq!(decode_cursor(""), None);
+        assert_eq!(decode_cursor("not base64!"), None);
+        assert_eq!(decode_cursor(&encode("42")), None);
+        assert_eq!(decode_cursor(&encode("id:")), None);
+        assert_eq!(decode_cursor(&encode("id:forty-two")), None);
+        assert_eq!(decode_cursor(&encode("id:99999999999")), None);
+        assert_eq!(decode_cursor(&base64::encode_config(&[0xff, 0xfe], base64::URL_SAFE)),
+                   None);
+    }
 }
@@ -0,0 +1,40 @@
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &'static str = "X-Request-Id";
+
+/// Tags every request with a fresh id and echoes it back in the response.
+///
+/// The id is stored as the request's `X-Request-Id` header (overwriting any
+/// client supplied value) so handlers, catchers and logs can all read it.
+pub struct RequestIdFairing;
+
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ID",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        request.replace_header(Header::new(REQUEST_ID_HEADER, Uuid::new_v4().to_string()));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        if let Some(id) = request_id(request) {
+            info!("[{}] {} {} => {}",
+                  id,
+                  request.method(),
+                  request.uri(),
+                  response.status());
+            response.set_raw_header(REQUEST_ID_HEADER, id.to_owned());
+        }
+    }
+}
+
+pub fn request_id<'a>(request: &'a Request) -> Option<&'a str> {
+    request.headers().get_one(REQUEST_ID_HEADER)
+}
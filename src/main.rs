@@ -14,7 +14,17 @@ extern crate r2d2_diesel;
 extern crate rocket;
 #[macro_use]
 extern crate rocket_contrib;
+extern crate serde;
 extern crate serde_json;
+extern crate chrono;
+extern crate crypto;
+extern crate base64;
+extern crate uuid;
+extern crate unidecode;
+extern crate pulldown_cmark;
+extern crate ammonia;
+#[macro_use]
+extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
@@ -27,22 +37,45 @@ mod endpoints;
 mod config;
 mod env;
 mod db;
+mod auth;
+mod roles;
+mod moderation;
+mod spam;
+mod validation;
+mod search;
+mod timestamps;
+mod scheduler;
+mod purge;
+mod slugs;
+mod tagging;
+mod deletion;
+mod revisions;
+mod diff;
+mod markdown;
+mod sanitization;
 
 mod endpoint_error;
 
 use env::Env;
-use config::DbConfig;
+use config::Config;
 use db::Db;
+use spam::SpamFilters;
 use endpoints::api_v1;
+use endpoints::request_id::RequestIdFairing;
 
 fn main() {
     let env_str = &std_env::var("BLOG_ENV").unwrap_or_else(|_| "development".to_owned());
     let env = Env::from_str(env_str).unwrap_or_default();
-    let db_config = DbConfig::load(&env).expect("Error loading DB configuration");
-    let mut db = Db::new(db_config);
+    let config = Config::load(&env).expect("Error loading configuration");
+    let mut db = Db::new(config.db().clone());
 
     match db.init() {
         Ok(_) => {
+            markdown::spawn(db.pool().clone(), config.sanitization().clone());
+            scheduler::spawn(db.pool().clone());
+            purge::spawn(db.pool().clone(), config.trash().clone());
+            let spam_filters = SpamFilters::with_defaults(config.spam());
+
             rocket::ignite()
                 .mount("/api/v1",
                        routes![
@@ -50,16 +83,30 @@ fn main() {
                 api_v1::posts::index_paginated,
                 api_v1::posts::create,
                 api_v1::posts::show,
+                api_v1::posts::show_by_slug,
                 api_v1::posts::update,
                 api_v1::posts::destroy,
+                api_v1::posts::publish,
+                api_v1::posts::unpublish,
                 api_v1::posts::user_posts_index,
+                api_v1::posts::user_posts_index_paginated,
                 api_v1::posts::user_post_show,
+                api_v1::posts::tag_posts_index,
+                api_v1::posts::tag_posts_index_paginated,
+                api_v1::revisions::index,
+                api_v1::revisions::index_paginated,
+                api_v1::revisions::show,
+                api_v1::revisions::compare,
+                api_v1::revisions::rollback,
                 api_v1::users::index,
                 api_v1::users::index_paginated,
                 api_v1::users::create,
                 api_v1::users::show,
                 api_v1::users::update,
                 api_v1::users::destroy,
+                api_v1::users::change_password,
+                api_v1::users::grant_role,
+                api_v1::users::revoke_role,
                 api_v1::comments::index,
                 api_v1::comments::index_paginated,
                 api_v1::comments::create,
@@ -67,10 +114,43 @@ fn main() {
                 api_v1::comments::update,
                 api_v1::comments::destroy,
                 api_v1::comments::post_comments_index,
+                api_v1::comments::post_comments_index_paginated,
                 api_v1::comments::user_comments_index,
+                api_v1::comments::user_comments_index_paginated,
                 api_v1::comments::post_comment_show,
+                api_v1::moderation::index,
+                api_v1::moderation::index_paginated,
+                api_v1::moderation::approve,
+                api_v1::moderation::reject,
+                api_v1::moderation::mark_as_spam,
+                api_v1::trash::posts_index,
+                api_v1::trash::posts_index_paginated,
+                api_v1::trash::users_index,
+                api_v1::trash::users_index_paginated,
+                api_v1::trash::comments_index,
+                api_v1::trash::comments_index_paginated,
+                api_v1::trash::restore_post,
+                api_v1::trash::restore_user,
+                api_v1::trash::restore_comment,
+                api_v1::sessions::create,
+                api_v1::search::index,
+                api_v1::tags::index,
+                api_v1::tags::create,
+                api_v1::tags::show,
+                api_v1::tags::update,
+                api_v1::tags::destroy,
+                api_v1::tags::post_tags_index,
             ])
+                .catch(errors![endpoints::catchers::bad_request,
+                               endpoints::catchers::unauthorized,
+                               endpoints::catchers::forbidden,
+                               endpoints::catchers::not_found,
+                               endpoints::catchers::unprocessable_entity,
+                               endpoints::catchers::internal_error])
+                .attach(RequestIdFairing)
                 .manage(db)
+                .manage(spam_filters)
+                .manage(config)
                 .launch()
         }
         Err(err) => println!("Db initialization error: {}", err),
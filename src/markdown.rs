@@ -0,0 +1,153 @@
+use std::thread;
+
+use ammonia;
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use pulldown_cmark::{html, Event, Parser};
+
+use config::SanitizationConfig;
+use db::{DbError, DbPool};
+use sanitization::SanitizationPolicy;
+use schema::{comments, posts};
+
+/// How many bodies the backfill renders per transaction.
+const BATCH_SIZE: i64 = 500;
+
+/// Renders a Markdown `source` to HTML safe to embed in a page.
+///
+/// Markdown lets raw HTML through. Under `PlainText` it is turned into text
+/// first. Either way the output goes through ammonia's whitelist: formatting,
+/// links, images, lists, tables and code survive, scripts, styles, event
+/// handlers and `javascript:` URLs do not.
+pub fn render(source: &str, policy: SanitizationPolicy) -> String {
+    let events = Parser::new(source).map(|event| match (policy, event) {
+        (SanitizationPolicy::PlainText, Event::Html(html)) |
+        (SanitizationPolicy::PlainText, Event::InlineHtml(html)) => Event::Text(html),
+        (_, event) => event,
+    });
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events);
+
+    ammonia::clean(&unsafe_html)
+}
+
+/// Starts the thread rendering the bodies of posts and comments that do not
+/// have HTML yet, which is every row written before bodies were rendered. It
+/// runs once, next to the server rather than before it, so a large backlog
+/// does not hold up boot. Rows rendered in the meantime are left alone.
+pub fn spawn(pool: DbPool, config: SanitizationConfig) {
+    thread::Builder::new()
+        .name(String::from("markdown-backfill"))
+        .spawn(move || match render_missing(&pool, &config) {
+            Ok(0) => {}
+            Ok(count) => info!("Rendered {} Markdown body(ies)", count),
+            Err(err) => error!("Error rendering Markdown bodies: {}", err),
+        })
+        .expect("Error spawning the Markdown backfill");
+}
+
+/// Works through the pending rows `BATCH_SIZE` at a time, one transaction per
+/// batch. Returns how many got rendered.
+fn render_missing(pool: &DbPool, config: &SanitizationConfig) -> Result<usize, DbError> {
+    let conn = &*pool.get()?;
+    let mut count = 0;
+
+    loop {
+        let rendered = render_posts_batch(conn, config.post_body)?;
+        if rendered == 0 {
+            break;
+        }
+        count += rendered;
+    }
+
+    loop {
+        let rendered = render_comments_batch(conn, config.comment_body)?;
+        if rendered == 0 {
+            break;
+        }
+        count += rendered;
+    }
+
+    Ok(count)
+}
+
+fn render_posts_batch(conn: &PgConnection, policy: SanitizationPolicy) -> Result<usize, DbError> {
+    let pending = posts::table.select((posts::id, posts::body))
+        .filter(posts::body_html.is_null())
+        .order(posts::id)
+        .limit(BATCH_SIZE)
+        .load::<(i32, String)>(conn)?;
+
+    let count = conn.transaction(|| {
+        let mut count = 0;
+        for &(id, ref body) in &pending {
+            count += diesel::update(posts::table.find(id))
+                .set(posts::body_html.eq(render(body, policy)))
+                .execute(conn)?;
+        }
+        Ok(count)
+    })?;
+
+    Ok(count)
+}
+
+fn render_comments_batch(conn: &PgConnection,
+                         policy: SanitizationPolicy)
+                         -> Result<usize, DbError> {
+    let pending = comments::table.select((comments::id, comments::body))
+        .filter(comments::body_html.is_null())
+        .order(comments::id)
+        .limit(BATCH_SIZE)
+        .load::<(i32, String)>(conn)?;
+
+    let count = conn.transaction(|| {
+        let mut count = 0;
+        for &(id, ref body) in &pending {
+            count += diesel::update(comments::table.find(id))
+                .set(comments::body_html.eq(render(body, policy)))
+                .execute(conn)?;
+        }
+        Ok(count)
+    })?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_markdown_formatting() {
+        let html = render("> *quoted*", SanitizationPolicy::LimitedHtml);
+
+        assert!(html.contains("<blockquote>"));
+        assert!(html.contains("<em>quoted</em>"));
+    }
+
+    #[test]
+    fn limited_html_keeps_whitelisted_tags_only() {
+        let html = render("<b>bold</b><script>alert(1)</script>", SanitizationPolicy::LimitedHtml);
+
+        assert!(html.contains("<b>bold</b>"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("alert"));
+    }
+
+    #[test]
+    fn plain_text_shows_html_as_text() {
+        let html = render("<b>bold</b> and *em*", SanitizationPolicy::PlainText);
+
+        assert!(html.contains("&lt;b&gt;bold&lt;/b&gt;"));
+        assert!(html.contains("<em>em</em>"));
+    }
+
+    #[test]
+    fn links_cannot_run_scripts() {
+        for policy in &[SanitizationPolicy::PlainText, SanitizationPolicy::LimitedHtml] {
+            assert!(!render("[x](javascript:void)", *policy).contains("javascript:"));
+        }
+    }
+}
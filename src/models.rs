@@ -1,27 +1,167 @@
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+
+use moderation::ModerationStatus;
+use roles::{Capability, Role};
+use timestamps;
+
 #[derive(Identifiable, Queryable, Associations, Serialize, Deserialize)]
 #[belongs_to(User)]
 #[has_many(comments)]
+#[has_many(post_slugs)]
+#[has_many(post_tags)]
+#[has_many(post_revisions)]
 pub struct Post {
     pub id: i32,
     pub title: String,
     pub body: String,
     pub published: bool,
     pub user_id: Option<i32>,
+    #[serde(with = "timestamps")]
+    pub created_at: NaiveDateTime,
+    #[serde(with = "timestamps")]
+    pub updated_at: NaiveDateTime,
+    #[serde(with = "timestamps::option")]
+    pub published_at: Option<NaiveDateTime>,
+    #[serde(with = "timestamps::option")]
+    pub publish_at: Option<NaiveDateTime>,
+    pub slug: String,
+    #[serde(with = "timestamps::option")]
+    pub deleted_at: Option<NaiveDateTime>,
+    /// `body` rendered from Markdown and sanitized. `body` itself is the
+    /// source as typed and must not be shown as HTML.
+    pub body_html: Option<String>,
 }
 
-#[derive(Insertable, Serialize, Deserialize)]
-#[table_name="posts"]
+#[derive(Serialize, Deserialize)]
 pub struct NewPost {
     pub title: String,
     pub body: String,
     pub user_id: Option<i32>,
+    #[serde(default, with = "timestamps::option")]
+    pub publish_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// Rendered from `body` when the request is sanitized.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub body_html: String,
 }
 
-#[derive(Serialize, Deserialize, AsChangeset)]
+#[derive(Insertable)]
 #[table_name="posts"]
+pub struct NewPostRecord<'a> {
+    pub title: &'a str,
+    pub body: &'a str,
+    pub user_id: Option<i32>,
+    pub publish_at: Option<NaiveDateTime>,
+    pub slug: String,
+    pub body_html: String,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct UpdatedPost {
     pub title: Option<String>,
     pub body: Option<String>,
+    pub user_id: Option<i32>,
+    #[serde(default, with = "timestamps::option")]
+    pub publish_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub body_html: Option<String>,
+}
+
+#[derive(AsChangeset)]
+#[table_name="posts"]
+pub struct UpdatedPostRecord<'a> {
+    pub title: Option<&'a str>,
+    pub body: Option<&'a str>,
+    pub user_id: Option<i32>,
+    pub publish_at: Option<NaiveDateTime>,
+    pub slug: Option<String>,
+    pub body_html: Option<String>,
+}
+
+/// A slug a post was previously reachable at.
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name="post_slugs"]
+#[belongs_to(Post)]
+pub struct PostSlug {
+    pub id: i32,
+    pub post_id: i32,
+    pub slug: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name="post_slugs"]
+pub struct NewPostSlug<'a> {
+    pub post_id: i32,
+    pub slug: &'a str,
+}
+
+/// The title and body a post had before one of its updates, and who made it.
+#[derive(Identifiable, Queryable, Associations, Serialize, Deserialize)]
+#[table_name="post_revisions"]
+#[belongs_to(Post)]
+pub struct PostRevision {
+    pub id: i32,
+    pub post_id: i32,
+    pub user_id: Option<i32>,
+    pub title: String,
+    pub body: String,
+    #[serde(with = "timestamps")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name="post_revisions"]
+pub struct NewPostRevision<'a> {
+    pub post_id: i32,
+    pub user_id: Option<i32>,
+    pub title: &'a str,
+    pub body: &'a str,
+}
+
+#[derive(Identifiable, Queryable, Associations, Serialize, Deserialize)]
+#[has_many(post_tags)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+    #[serde(with = "timestamps")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Serialize, Deserialize)]
+#[table_name="tags"]
+pub struct NewTag {
+    pub name: String,
+}
+
+/// A tag with the number of published posts carrying it, for tag clouds.
+#[derive(Queryable, Serialize)]
+pub struct TagCount {
+    pub id: i32,
+    pub name: String,
+    pub post_count: i64,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name="post_tags"]
+#[belongs_to(Post)]
+#[belongs_to(Tag)]
+pub struct PostTag {
+    pub id: i32,
+    pub post_id: i32,
+    pub tag_id: i32,
+}
+
+#[derive(Insertable)]
+#[table_name="post_tags"]
+pub struct NewPostTag {
+    pub post_id: i32,
+    pub tag_id: i32,
 }
 
 #[derive(Identifiable, Queryable, Associations, Serialize, Deserialize)]
@@ -32,14 +172,43 @@ pub struct User {
     pub name: String,
     pub username: String,
     pub email: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub password_hash: String,
+    pub role: String,
+    #[serde(with = "timestamps")]
+    pub created_at: NaiveDateTime,
+    #[serde(with = "timestamps")]
+    pub updated_at: NaiveDateTime,
+    #[serde(with = "timestamps::option")]
+    pub deleted_at: Option<NaiveDateTime>,
+    pub placeholder: bool,
 }
 
-#[derive(Insertable, Serialize, Deserialize)]
-#[table_name="users"]
+impl User {
+    pub fn role(&self) -> Role {
+        Role::from_str(&self.role).unwrap_or_default()
+    }
+
+    pub fn can(&self, capability: Capability) -> bool {
+        self.role().can(capability)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct NewUser {
     pub name: String,
     pub username: String,
     pub email: String,
+    pub password: String,
+}
+
+#[derive(Insertable)]
+#[table_name="users"]
+pub struct NewUserRecord<'a> {
+    pub name: &'a str,
+    pub username: &'a str,
+    pub email: &'a str,
+    pub password_hash: String,
 }
 
 #[derive(Serialize, Deserialize, AsChangeset)]
@@ -50,6 +219,17 @@ pub struct UpdatedUser {
     pub email: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct RoleAssignment {
+    pub role: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordChange {
+    pub old_password: String,
+    pub new_password: String,
+}
+
 #[derive(Identifiable, Queryable, Associations, Serialize, Deserialize)]
 #[belongs_to(User)]
 #[belongs_to(Post)]
@@ -59,25 +239,77 @@ pub struct Comment {
     pub published: bool,
     pub user_id: i32,
     pub post_id: i32,
+    #[serde(with = "timestamps")]
+    pub created_at: NaiveDateTime,
+    #[serde(with = "timestamps")]
+    pub updated_at: NaiveDateTime,
+    pub parent_id: Option<i32>,
+    pub status: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub ip_address: Option<String>,
+    #[serde(with = "timestamps::option")]
+    pub deleted_at: Option<NaiveDateTime>,
+    /// `body` rendered from Markdown and sanitized. `body` itself is the
+    /// source as typed and must not be shown as HTML.
+    pub body_html: Option<String>,
 }
 
-#[derive(Insertable, Serialize, Deserialize)]
-#[table_name="comments"]
+impl Comment {
+    pub fn status(&self) -> ModerationStatus {
+        ModerationStatus::from_str(&self.status).unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct NewComment {
     pub body: String,
     pub user_id: i32,
     pub post_id: i32,
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+    /// Rendered from `body` when the request is sanitized.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub body_html: String,
+}
+
+#[derive(Insertable)]
+#[table_name="comments"]
+pub struct NewCommentRecord<'a> {
+    pub body: &'a str,
+    pub user_id: i32,
+    pub post_id: i32,
+    pub parent_id: Option<i32>,
+    pub status: String,
+    pub published: bool,
+    pub ip_address: Option<String>,
+    pub body_html: String,
+}
+
+/// A comment along with its replies, as returned by threaded listings.
+#[derive(Serialize)]
+pub struct CommentNode {
+    pub comment: Comment,
+    pub replies: Vec<CommentNode>,
 }
 
 #[derive(Serialize, Deserialize, AsChangeset)]
 #[table_name="comments"]
 pub struct UpdatedComment {
     pub body: Option<String>,
-    pub published: Option<bool>,
     pub user_id: Option<i32>,
     pub post_id: Option<i32>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub body_html: Option<String>,
+    /// Set again when a new body is screened, never from the request.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub status: Option<String>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub published: Option<bool>,
 }
 
 use super::schema::posts;
+use super::schema::post_slugs;
+use super::schema::tags;
+use super::schema::post_tags;
 use super::schema::users;
 use super::schema::comments;
@@ -0,0 +1,137 @@
+use std::str::FromStr;
+use std::string::ToString;
+use std::default::Default;
+use std::fmt;
+
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+
+use models::User;
+use roles::Capability;
+use schema::comments;
+
+/// Where a comment stands in the moderation queue. Only approved comments
+/// are published.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModerationStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Spam,
+}
+
+#[derive(Debug)]
+pub enum ParseModerationStatusError {
+    UnknownStatus,
+}
+
+impl fmt::Display for ParseModerationStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseModerationStatusError::UnknownStatus => write!(f, "Unknown moderation status"),
+        }
+    }
+}
+
+impl FromStr for ModerationStatus {
+    type Err = ParseModerationStatusError;
+
+    fn from_str(s: &str) -> Result<ModerationStatus, Self::Err> {
+        match s {
+            "pending" => Ok(ModerationStatus::Pending),
+            "approved" => Ok(ModerationStatus::Approved),
+            "rejected" => Ok(ModerationStatus::Rejected),
+            "spam" => Ok(ModerationStatus::Spam),
+            _ => Err(ParseModerationStatusError::UnknownStatus),
+        }
+    }
+}
+
+impl Default for ModerationStatus {
+    fn default() -> ModerationStatus {
+        ModerationStatus::Pending
+    }
+}
+
+impl ToString for ModerationStatus {
+    fn to_string(&self) -> String {
+        match *self {
+            ModerationStatus::Pending => String::from("pending"),
+            ModerationStatus::Approved => String::from("approved"),
+            ModerationStatus::Rejected => String::from("rejected"),
+            ModerationStatus::Spam => String::from("spam"),
+        }
+    }
+}
+
+impl ModerationStatus {
+    /// Pending comments can go anywhere; decisions can be reversed, but a
+    /// comment never goes back to the queue.
+    pub fn can_become(&self, target: ModerationStatus) -> bool {
+        match (*self, target) {
+            (ModerationStatus::Pending, ModerationStatus::Pending) => false,
+            (ModerationStatus::Pending, _) => true,
+            (ModerationStatus::Approved, ModerationStatus::Rejected) |
+            (ModerationStatus::Approved, ModerationStatus::Spam) |
+            (ModerationStatus::Rejected, ModerationStatus::Approved) |
+            (ModerationStatus::Spam, ModerationStatus::Approved) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_published(&self) -> bool {
+        *self == ModerationStatus::Approved
+    }
+}
+
+/// The status a new comment by `user` starts in: moderators and users who
+/// already had a comment approved skip the queue.
+pub fn initial_status(conn: &PgConnection, user: &User) -> QueryResult<ModerationStatus> {
+    if user.can(Capability::ModerateComments) {
+        return Ok(ModerationStatus::Approved);
+    }
+
+    let approved = comments::table.filter(comments::user_id.eq(user.id))
+        .filter(comments::status.eq(ModerationStatus::Approved.to_string()))
+        .count()
+        .get_result::<i64>(conn)?;
+
+    if approved > 0 {
+        Ok(ModerationStatus::Approved)
+    } else {
+        Ok(ModerationStatus::Pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::ModerationStatus::*;
+
+    #[test]
+    fn pending_comments_can_go_anywhere_but_pending() {
+        assert!(!Pending.can_become(Pending));
+        assert!(Pending.can_become(Approved));
+        assert!(Pending.can_become(Rejected));
+        assert!(Pending.can_become(Spam));
+    }
+
+    #[test]
+    fn decisions_can_be_reversed() {
+        assert!(Approved.can_become(Rejected));
+        assert!(Approved.can_become(Spam));
+        assert!(Rejected.can_become(Approved));
+        assert!(Spam.can_become(Approved));
+    }
+
+    #[test]
+    fn comments_never_go_back_to_the_queue() {
+        for status in &[Approved, Rejected, Spam] {
+            assert!(!status.can_become(Pending));
+            assert!(!status.can_become(*status));
+        }
+        assert!(!Rejected.can_become(Spam));
+        assert!(!Spam.can_become(Rejected));
+    }
+}
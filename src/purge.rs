@@ -0,0 +1,61 @@
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use chrono::Duration;
+use diesel;
+use diesel::expression::dsl::sql;
+use diesel::prelude::*;
+use diesel::types::Bool;
+
+use config::TrashConfig;
+use db::{DbError, DbPool};
+use schema::{comments, posts, users};
+use timestamps;
+
+/// Users are only purged once nothing references them anymore.
+const WITHOUT_CONTENT: &'static str = "NOT EXISTS (SELECT 1 FROM posts \
+                                                   WHERE posts.user_id = users.id) \
+                                       AND NOT EXISTS (SELECT 1 FROM comments \
+                                                       WHERE comments.user_id = users.id)";
+
+/// Starts the thread hard-deleting rows that have been in the trash for longer
+/// than the configured retention period.
+pub fn spawn(pool: DbPool, config: TrashConfig) {
+    thread::Builder::new()
+        .name(String::from("trash-purge"))
+        .spawn(move || loop {
+            match purge_expired(&pool, config.retention_days) {
+                Ok(0) => {}
+                Ok(count) => info!("Purged {} deleted record(s)", count),
+                Err(err) => error!("Error purging deleted records: {}", err),
+            }
+
+            thread::sleep(StdDuration::from_secs(config.purge_interval));
+        })
+        .expect("Error spawning the trash purge");
+}
+
+/// Comments go first, as they reference both posts and users, then posts and
+/// finally users. All of it in one transaction. Live replies to a purged
+/// comment stay, their `parent_id` is set to NULL by the foreign key.
+fn purge_expired(pool: &DbPool, retention_days: i64) -> Result<usize, DbError> {
+    let conn = &*pool.get()?;
+    let cutoff = timestamps::now() - Duration::days(retention_days);
+
+    let count = conn.transaction(|| {
+        let expired_posts = posts::table.select(posts::id).filter(posts::deleted_at.lt(cutoff));
+
+        let comments = diesel::delete(comments::table.filter(comments::deleted_at.lt(cutoff)
+                .or(comments::post_id.eq_any(expired_posts))))
+            .execute(conn)?;
+        let posts = diesel::delete(posts::table.filter(posts::deleted_at.lt(cutoff)))
+            .execute(conn)?;
+        let users = diesel::delete(users::table.filter(users::deleted_at.lt(cutoff))
+                .filter(sql::<Bool>(WITHOUT_CONTENT)))
+            .execute(conn)?;
+
+        Ok(comments + posts + users)
+    })?;
+
+    Ok(count)
+}
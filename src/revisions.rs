@@ -0,0 +1,26 @@
+use diesel;
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+
+use models::{NewPostRevision, Post, PostRevision};
+use schema::post_revisions;
+
+/// Keeps `post`'s title and body as they are now, before `user_id` changes
+/// them.
+pub fn record(conn: &PgConnection, post: &Post, user_id: i32) -> QueryResult<PostRevision> {
+    diesel::insert(&NewPostRevision {
+            post_id: post.id,
+            user_id: Some(user_id),
+            title: &post.title,
+            body: &post.body,
+        })
+        .into(post_revisions::table)
+        .get_result::<PostRevision>(conn)
+}
+
+/// Revision `id` of the post `post_id`.
+pub fn find(conn: &PgConnection, post_id: i32, id: i32) -> QueryResult<PostRevision> {
+    post_revisions::table.find(id)
+        .filter(post_revisions::post_id.eq(post_id))
+        .first::<PostRevision>(conn)
+}
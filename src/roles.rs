@@ -0,0 +1,87 @@
+use std::str::FromStr;
+use std::string::ToString;
+use std::default::Default;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Admin,
+    Editor,
+    Author,
+    Reader,
+}
+
+/// Things a user may be allowed to do regardless of who owns the resource.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+    ManageUsers,
+    EditAnyPost,
+    ModerateComments,
+    WritePosts,
+    WriteComments,
+}
+
+#[derive(Debug)]
+pub enum ParseRoleError {
+    UnknownRole,
+}
+
+impl fmt::Display for ParseRoleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseRoleError::UnknownRole => write!(f, "Unknown role"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ParseRoleError;
+
+    fn from_str(s: &str) -> Result<Role, Self::Err> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "editor" => Ok(Role::Editor),
+            "author" => Ok(Role::Author),
+            "reader" => Ok(Role::Reader),
+            _ => Err(ParseRoleError::UnknownRole),
+        }
+    }
+}
+
+impl Default for Role {
+    fn default() -> Role {
+        Role::Reader
+    }
+}
+
+impl ToString for Role {
+    fn to_string(&self) -> String {
+        match *self {
+            Role::Admin => String::from("admin"),
+            Role::Editor => String::from("editor"),
+            Role::Author => String::from("author"),
+            Role::Reader => String::from("reader"),
+        }
+    }
+}
+
+impl Role {
+    pub fn can(&self, capability: Capability) -> bool {
+        match *self {
+            Role::Admin => true,
+            Role::Editor => {
+                match capability {
+                    Capability::ManageUsers => false,
+                    _ => true,
+                }
+            }
+            Role::Author => {
+                match capability {
+                    Capability::WritePosts | Capability::WriteComments => true,
+                    _ => false,
+                }
+            }
+            Role::Reader => capability == Capability::WriteComments,
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Sanitization of user-supplied text, applied as request bodies are
+//! deserialized: handlers take a `Sanitized<NewPost>` where they would take a
+//! `Json<NewPost>`.
+//!
+//! Each field is cleaned with the policy `SanitizationConfig` gives it and is
+//! stored as it comes out, so the API returns what was stored. Titles, names
+//! and tags are cleaned as they are. Markdown bodies keep their source, which
+//! must not be shown as HTML; their policy applies to the `body_html` they are
+//! rendered to.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::string::ToString;
+use std::ops::Deref;
+use std::fmt;
+
+use ammonia;
+use ammonia::Builder;
+use rocket::{Data, Outcome, Request, State};
+use rocket::data::{self, FromData};
+use rocket::http::Status;
+use rocket_contrib::{Json, SerdeError};
+use serde::de::{DeserializeOwned, Error};
+
+use config::{Config, SanitizationConfig};
+use markdown;
+use models::{NewComment, NewPost, NewTag, NewUser};
+use models::{UpdatedComment, UpdatedPost, UpdatedUser};
+
+/// How much markup a user-supplied text field may keep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SanitizationPolicy {
+    /// None, the field is text. Markdown formatting still applies to bodies.
+    PlainText,
+    /// ammonia's whitelist of formatting tags, no scripts, styles, event
+    /// handlers or `javascript:` URLs.
+    LimitedHtml,
+}
+
+#[derive(Debug)]
+pub enum ParseSanitizationPolicyError {
+    UnknownPolicy,
+}
+
+impl fmt::Display for ParseSanitizationPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseSanitizationPolicyError::UnknownPolicy => {
+                write!(f, "Unknown sanitization policy")
+            }
+        }
+    }
+}
+
+impl FromStr for SanitizationPolicy {
+    type Err = ParseSanitizationPolicyError;
+
+    fn from_str(s: &str) -> Result<SanitizationPolicy, Self::Err> {
+        match s {
+            "plain_text" => Ok(SanitizationPolicy::PlainText),
+            "limited_html" => Ok(SanitizationPolicy::LimitedHtml),
+            _ => Err(ParseSanitizationPolicyError::UnknownPolicy),
+        }
+    }
+}
+
+impl ToString for SanitizationPolicy {
+    fn to_string(&self) -> String {
+        match *self {
+            SanitizationPolicy::PlainText => String::from("plain_text"),
+            SanitizationPolicy::LimitedHtml => String::from("limited_html"),
+        }
+    }
+}
+
+/// Cleans a title, name or tag according to `policy`.
+///
+/// `PlainText` removes the markup and keeps the rest as typed, "Rust < C++"
+/// and "&amp;" included. `LimitedHtml` gives HTML, with entities escaped.
+/// Cleaning a clean value changes nothing, so values read from the API can be
+/// written back as they are.
+pub fn clean(policy: SanitizationPolicy, text: &str) -> String {
+    match policy {
+        SanitizationPolicy::PlainText => strip_markup(text),
+        SanitizationPolicy::LimitedHtml => ammonia::clean(text),
+    }
+}
+
+/// The text of `text` read as HTML, taken again until no markup is left:
+/// removing a tag can put another one together, as in `<<b>b>`. `&` is
+/// escaped first so that entities are kept as typed rather than decoded.
+fn strip_markup(text: &str) -> String {
+    let mut text = text.to_owned();
+
+    loop {
+        let html = Builder::default()
+            .tags(HashSet::new())
+            .clean(&text.replace('&', "&amp;"))
+            .to_string();
+        let stripped = unescape(&html);
+
+        if stripped == text {
+            return stripped;
+        }
+        text = stripped;
+    }
+}
+
+/// Reverses the escaping of text by the HTML serializer, `&amp;` last so that
+/// an escaped entity is not decoded twice.
+fn unescape(html: &str) -> String {
+    html.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&amp;", "&")
+}
+
+/// Removes control characters other than line breaks and tabs, which Markdown
+/// bodies have no use for and Postgres may refuse.
+fn remove_control_characters(text: &str) -> String {
+    text.chars()
+        .filter(|&c| !c.is_control() || c == '\n' || c == '\r' || c == '\t')
+        .collect()
+}
+
+fn clean_option(policy: SanitizationPolicy, text: &mut Option<String>) {
+    if let Some(ref mut text) = *text {
+        *text = clean(policy, text);
+    }
+}
+
+fn clean_tags(policy: SanitizationPolicy, names: &mut Option<Vec<String>>) {
+    if let Some(ref mut names) = *names {
+        for name in names.iter_mut() {
+            *name = clean(policy, name);
+        }
+    }
+}
+
+/// Request bodies carrying user-supplied text.
+pub trait Sanitize {
+    fn sanitize(&mut self, config: &SanitizationConfig);
+}
+
+impl Sanitize for NewPost {
+    fn sanitize(&mut self, config: &SanitizationConfig) {
+        self.title = clean(config.post_title, &self.title);
+        self.body = remove_control_characters(&self.body);
+        self.body_html = markdown::render(&self.body, config.post_body);
+        clean_tags(config.tag_name, &mut self.tags);
+    }
+}
+
+impl Sanitize for UpdatedPost {
+    fn sanitize(&mut self, config: &SanitizationConfig) {
+        clean_option(config.post_title, &mut self.title);
+        self.body = self.body.as_ref().map(|body| remove_control_characters(body));
+        self.body_html = self.body.as_ref().map(|body| markdown::render(body, config.post_body));
+        clean_tags(config.tag_name, &mut self.tags);
+    }
+}
+
+impl Sanitize for NewComment {
+    fn sanitize(&mut self, config: &SanitizationConfig) {
+        self.body = remove_control_characters(&self.body);
+        self.body_html = markdown::render(&self.body, config.comment_body);
+    }
+}
+
+impl Sanitize for UpdatedComment {
+    fn sanitize(&mut self, config: &SanitizationConfig) {
+        self.body = self.body.as_ref().map(|body| remove_control_characters(body));
+        self.body_html = self.body
+            .as_ref()
+            .map(|body| markdown::render(body, config.comment_body));
+    }
+}
+
+/// Usernames and emails are plain text whatever the configuration, validation
+/// narrows them down further.
+impl Sanitize for NewUser {
+    fn sanitize(&mut self, config: &SanitizationConfig) {
+        self.name = clean(config.user_name, &self.name);
+        self.username = clean(SanitizationPolicy::PlainText, &self.username);
+        self.email = clean(SanitizationPolicy::PlainText, &self.email);
+    }
+}
+
+impl Sanitize for UpdatedUser {
+    fn sanitize(&mut self, config: &SanitizationConfig) {
+        clean_option(config.user_name, &mut self.name);
+        clean_option(SanitizationPolicy::PlainText, &mut self.username);
+        clean_option(SanitizationPolicy::PlainText, &mut self.email);
+    }
+}
+
+impl Sanitize for NewTag {
+    fn sanitize(&mut self, config: &SanitizationConfig) {
+        self.name = clean(config.tag_name, &self.name);
+    }
+}
+
+/// A JSON request body, sanitized with the configured policies before the
+/// handler sees it, and so before it gets validated.
+pub struct Sanitized<T>(pub T);
+
+impl<T: DeserializeOwned + Sanitize> FromData for Sanitized<T> {
+    type Error = SerdeError;
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Sanitized<T>, SerdeError> {
+        let config = match request.guard::<State<Config>>() {
+            Outcome::Success(config) => config,
+            _ => {
+                let err = SerdeError::custom("Config is not managed by Rocket");
+                return Outcome::Failure((Status::InternalServerError, err));
+            }
+        };
+
+        Json::<T>::from_data(request, data).map(|json| {
+            let mut value = json.into_inner();
+            value.sanitize(config.sanitization());
+
+            Sanitized(value)
+        })
+    }
+}
+
+impl<T> Deref for Sanitized<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::SanitizationPolicy::*;
+
+    #[test]
+    fn plain_text_removes_every_tag() {
+        assert_eq!(clean(PlainText, "<b>bold</b> <i>text</i>"), "bold text");
+        assert_eq!(clean(PlainText, "<script>alert(1)</script>safe"), "safe");
+    }
+
+    #[test]
+    fn plain_text_keeps_the_rest_as_typed() {
+        for text in &["Rust < C++", "A & B", "a &amp; b", "> quoted", "1 <= 2 && 3 > 2"] {
+            assert_eq!(clean(PlainText, text), *text);
+        }
+    }
+
+    #[test]
+    fn plain_text_does_not_let_tags_reassemble() {
+        assert_eq!(clean(PlainText, "<<b>b>bold"), "bold");
+        assert_eq!(clean(PlainText, "<<i>img src=x onerror=alert(1)>"), "");
+    }
+
+    #[test]
+    fn limited_html_keeps_formatting_only() {
+        assert_eq!(clean(LimitedHtml, "<b onclick=\"steal()\">bold</b><script>alert(1)</script>"),
+                   "<b>bold</b>");
+        assert_eq!(clean(LimitedHtml, "A & B"), "A &amp; B");
+    }
+
+    #[test]
+    fn cleaning_twice_changes_nothing() {
+        let texts = ["A & B", "a &amp; b", "Rust < C++", "<b>x</b> &lt;y&gt;", "<<b>b>", "> q"];
+
+        for policy in &[PlainText, LimitedHtml] {
+            for text in &texts {
+                let cleaned = clean(*policy, text);
+                assert_eq!(clean(*policy, &cleaned), cleaned);
+            }
+        }
+    }
+
+    #[test]
+    fn removes_control_characters_only() {
+        assert_eq!(remove_control_characters("a\u{0}b\u{1b}c\u{7f}"), "abc");
+        assert_eq!(remove_control_characters("line\r\n\tindented <b>"), "line\r\n\tindented <b>");
+    }
+
+    #[test]
+    fn parses_the_policies_it_prints() {
+        for policy in &[PlainText, LimitedHtml] {
+            assert_eq!(policy.to_string().parse::<SanitizationPolicy>().unwrap(), *policy);
+        }
+    }
+}
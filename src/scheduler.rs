@@ -0,0 +1,49 @@
+use std::thread;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use diesel;
+use diesel::prelude::*;
+
+use db::{DbError, DbPool};
+use schema::posts::dsl::*;
+use timestamps;
+
+const POLL_INTERVAL_SECS: u64 = 30;
+
+/// Starts the thread publishing posts whose `publish_at` has passed.
+///
+/// It shares the server's pool and polls, so a post goes live at most
+/// `POLL_INTERVAL_SECS` late.
+pub fn spawn(pool: DbPool) {
+    thread::Builder::new()
+        .name(String::from("post-scheduler"))
+        .spawn(move || loop {
+            match publish_due_posts(&pool) {
+                Ok(0) => {}
+                Ok(count) => info!("Published {} scheduled post(s)", count),
+                Err(err) => error!("Error publishing scheduled posts: {}", err),
+            }
+
+            thread::sleep(Duration::from_secs(POLL_INTERVAL_SECS));
+        })
+        .expect("Error spawning the post scheduler");
+}
+
+fn publish_due_posts(pool: &DbPool) -> Result<usize, DbError> {
+    let conn = &*pool.get()?;
+    let now = timestamps::now();
+
+    let due_posts = posts.filter(published.eq(false))
+        .filter(publish_at.le(now))
+        .filter(deleted_at.is_null());
+
+    let count = diesel::update(due_posts)
+        .set((published.eq(true),
+              published_at.eq(publish_at),
+              publish_at.eq(None::<NaiveDateTime>),
+              updated_at.eq(now)))
+        .execute(conn)?;
+
+    Ok(count)
+}
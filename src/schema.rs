@@ -5,6 +5,49 @@ table! {
         body -> VarChar,
         published -> Bool,
         user_id -> Nullable<Integer>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        published_at -> Nullable<Timestamp>,
+        publish_at -> Nullable<Timestamp>,
+        slug -> VarChar,
+        deleted_at -> Nullable<Timestamp>,
+        body_html -> Nullable<VarChar>,
+    }
+}
+
+table! {
+    post_slugs {
+        id -> Integer,
+        post_id -> Integer,
+        slug -> VarChar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    post_revisions {
+        id -> Integer,
+        post_id -> Integer,
+        user_id -> Nullable<Integer>,
+        title -> VarChar,
+        body -> VarChar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    tags {
+        id -> Integer,
+        name -> VarChar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    post_tags {
+        id -> Integer,
+        post_id -> Integer,
+        tag_id -> Integer,
     }
 }
 
@@ -14,6 +57,12 @@ table! {
         username -> VarChar,
         name -> VarChar,
         email -> VarChar,
+        password_hash -> VarChar,
+        role -> VarChar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+        placeholder -> Bool,
     }
 }
 
@@ -24,5 +73,12 @@ table! {
         published -> Bool,
         user_id -> Integer,
         post_id -> Integer,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        parent_id -> Nullable<Integer>,
+        status -> VarChar,
+        ip_address -> Nullable<VarChar>,
+        deleted_at -> Nullable<Timestamp>,
+        body_html -> Nullable<VarChar>,
     }
 }
@@ -0,0 +1,140 @@
+use diesel::expression::{AsExpression, Expression, SqlLiteral};
+use diesel::expression::dsl::sql;
+use diesel::pg::{Pg, PgTypeMetadata};
+use diesel::types::{Float, HasSqlType, NotNull, Text};
+
+/// Postgres' `tsvector`.
+#[derive(Clone, Copy)]
+pub struct TsVector;
+
+/// Postgres' `tsquery`.
+#[derive(Clone, Copy)]
+pub struct TsQuery;
+
+/// Postgres' `regconfig`, the text search configuration.
+#[derive(Clone, Copy)]
+pub struct RegConfig;
+
+impl HasSqlType<TsVector> for Pg {
+    fn metadata() -> PgTypeMetadata {
+        PgTypeMetadata {
+            oid: 3614,
+            array_oid: 3643,
+        }
+    }
+}
+
+impl HasSqlType<TsQuery> for Pg {
+    fn metadata() -> PgTypeMetadata {
+        PgTypeMetadata {
+            oid: 3615,
+            array_oid: 3645,
+        }
+    }
+}
+
+impl HasSqlType<RegConfig> for Pg {
+    fn metadata() -> PgTypeMetadata {
+        PgTypeMetadata {
+            oid: 3734,
+            array_oid: 3735,
+        }
+    }
+}
+
+impl NotNull for TsVector {}
+impl NotNull for TsQuery {}
+impl NotNull for RegConfig {}
+
+/// The columns only the search endpoint needs. They live apart from
+/// `schema.rs` so the `search_vector`s, kept up to date by triggers, are not
+/// loaded with every `Post` and `Comment`.
+pub mod schema {
+    table! {
+        posts {
+            id -> Integer,
+            title -> VarChar,
+            body -> VarChar,
+            published -> Bool,
+            search_vector -> ::search::TsVector,
+            deleted_at -> Nullable<Timestamp>,
+        }
+    }
+
+    table! {
+        comments {
+            id -> Integer,
+            body -> VarChar,
+            published -> Bool,
+            post_id -> Integer,
+            search_vector -> ::search::TsVector,
+            deleted_at -> Nullable<Timestamp>,
+        }
+    }
+}
+
+sql_function!(plainto_tsquery, plainto_tsquery_t, (config: RegConfig, query: Text) -> TsQuery);
+sql_function!(ts_rank, ts_rank_t, (vector: TsVector, query: TsQuery) -> Float);
+sql_function!(ts_headline,
+              ts_headline_t,
+              (config: RegConfig, document: Text, query: TsQuery, options: Text) -> Text);
+
+infix_predicate!(Matches, " @@ ", backend: Pg);
+
+/// `ts_headline` options marking the matches with private use characters,
+/// which `highlight` turns into `<mark>` tags once the rest is escaped.
+pub const HEADLINE_OPTIONS: &'static str = "MaxFragments=2, MaxWords=30, MinWords=10, \
+                                            StartSel=\u{e000}, StopSel=\u{e001}";
+
+const START_SEL: char = '\u{e000}';
+const STOP_SEL: char = '\u{e001}';
+
+/// Turns a headline built with `HEADLINE_OPTIONS` into HTML: whatever markup
+/// the body had is escaped, only the matches are wrapped in `<mark>`.
+pub fn highlight(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len());
+
+    for c in headline.chars() {
+        match c {
+            START_SEL => html.push_str("<mark>"),
+            STOP_SEL => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#x27;"),
+            c => html.push(c),
+        }
+    }
+
+    html
+}
+
+/// The configuration the `search_vector` columns are generated with.
+pub fn english() -> SqlLiteral<RegConfig> {
+    sql::<RegConfig>("'english'::regconfig")
+}
+
+pub trait TsVectorExpressionMethods: Expression<SqlType = TsVector> + Sized {
+    fn matches<T: AsExpression<TsQuery>>(self, query: T) -> Matches<Self, T::Expression> {
+        Matches::new(self, query.as_expression())
+    }
+}
+
+impl<T: Expression<SqlType = TsVector>> TsVectorExpressionMethods for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlight_marks_the_matches() {
+        assert_eq!(highlight("a \u{e000}rusty\u{e001} crate"), "a <mark>rusty</mark> crate");
+    }
+
+    #[test]
+    fn highlight_escapes_the_body_markup() {
+        assert_eq!(highlight("<img src=x onerror=\u{e000}alert\u{e001}(1)> & co"),
+                   "&lt;img src=x onerror=<mark>alert</mark>(1)&gt; &amp; co");
+    }
+}
@@ -0,0 +1,105 @@
+use diesel;
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use unidecode::unidecode;
+
+use models::{NewPostSlug, Post};
+use schema::post_slugs;
+use schema::posts;
+
+const MAX_SLUG_LENGTH: usize = 80;
+const FALLBACK_SLUG: &'static str = "post";
+
+/// Turns `title` into a URL friendly slug: transliterated to ASCII, lowercased
+/// and with every run of other characters collapsed into a single dash.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+
+    for c in unidecode(title).to_lowercase().chars() {
+        match c {
+            'a'...'z' | '0'...'9' => slug.push(c),
+            _ if !slug.is_empty() && !slug.ends_with('-') => slug.push('-'),
+            _ => {}
+        }
+    }
+
+    slug.truncate(MAX_SLUG_LENGTH);
+    let slug = slug.trim_matches('-');
+
+    if slug.is_empty() {
+        FALLBACK_SLUG.to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+/// The first of `base`, `base-2`, `base-3`... not used by another post, either
+/// currently or in its slug history (old slugs keep redirecting).
+///
+/// The unique indexes still guard against two requests racing for a slug.
+pub fn unique_slug(conn: &PgConnection, base: &str, post_id: Option<i32>) -> QueryResult<String> {
+    let pattern = format!("{}-%", base);
+    let post_id = post_id.unwrap_or(0);
+
+    let mut taken = posts::table.select(posts::slug)
+        .filter(posts::slug.eq(base).or(posts::slug.like(pattern.clone())))
+        .filter(posts::id.ne(post_id))
+        .load::<String>(conn)?;
+    taken.extend(post_slugs::table.select(post_slugs::slug)
+        .filter(post_slugs::slug.eq(base).or(post_slugs::slug.like(pattern)))
+        .filter(post_slugs::post_id.ne(post_id))
+        .load::<String>(conn)?);
+
+    let slug = (1..)
+        .map(|n| if n == 1 { base.to_owned() } else { format!("{}-{}", base, n) })
+        .find(|candidate| !taken.contains(candidate))
+        .expect("Ran out of slug suffixes");
+
+    Ok(slug)
+}
+
+/// Moves `post`'s current slug to its history, making way for `new_slug`.
+///
+/// Renaming back to an old slug takes it out of the history.
+pub fn retire(conn: &PgConnection, post: &Post, new_slug: &str) -> QueryResult<()> {
+    diesel::delete(post_slugs::table.filter(post_slugs::slug.eq(new_slug))).execute(conn)?;
+    diesel::insert(&NewPostSlug {
+            post_id: post.id,
+            slug: &post.slug,
+        })
+        .into(post_slugs::table)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowercases_and_dashes_words() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Rust -- 2017  edition "), "rust-2017-edition");
+    }
+
+    #[test]
+    fn transliterates_to_ascii() {
+        assert_eq!(slugify("Crème Brûlée"), "creme-brulee");
+        assert_eq!(slugify("Straße"), "strasse");
+    }
+
+    #[test]
+    fn falls_back_when_nothing_is_left() {
+        assert_eq!(slugify(""), FALLBACK_SLUG);
+        assert_eq!(slugify("?!"), FALLBACK_SLUG);
+    }
+
+    #[test]
+    fn truncates_long_titles_without_a_trailing_dash() {
+        let slug = slugify(&"word ".repeat(40));
+
+        assert!(slug.len() <= MAX_SLUG_LENGTH);
+        assert!(!slug.ends_with('-'));
+    }
+}
@@ -0,0 +1,208 @@
+use chrono::Duration;
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+
+use config::SpamConfig;
+use moderation::ModerationStatus;
+use schema::comments;
+use timestamps;
+
+const LINK_MARKERS: &'static [&'static str] = &["http://", "https://", "www."];
+
+// What each heuristic adds to the score when it trips.
+const EXCESS_LINK_SCORE: f64 = 0.25;
+const BANNED_WORD_SCORE: f64 = 0.5;
+const REPEATED_CONTENT_SCORE: f64 = 0.6;
+const VELOCITY_SCORE: f64 = 0.6;
+
+/// A comment about to be created, as seen by the spam filters.
+pub struct Candidate<'a> {
+    pub body: &'a str,
+    pub user_id: i32,
+    pub post_id: i32,
+    pub ip_address: Option<&'a str>,
+}
+
+/// Rates how likely a new comment is to be spam.
+pub trait SpamFilter: Send + Sync {
+    /// A score from 0.0 (clean) to 1.0 (certainly spam).
+    fn score(&self, conn: &PgConnection, candidate: &Candidate) -> QueryResult<f64>;
+}
+
+/// The registered filters plus the thresholds turning their score into a
+/// moderation status. A comment's score is the highest any filter gives it.
+pub struct SpamFilters {
+    filters: Vec<Box<SpamFilter>>,
+    spam_threshold: f64,
+    review_threshold: f64,
+}
+
+impl SpamFilters {
+    pub fn new(config: &SpamConfig) -> SpamFilters {
+        SpamFilters {
+            filters: Vec::new(),
+            spam_threshold: config.spam_threshold,
+            review_threshold: config.review_threshold,
+        }
+    }
+
+    /// The built-in heuristics, which is what the server runs with.
+    pub fn with_defaults(config: &SpamConfig) -> SpamFilters {
+        SpamFilters::new(config).register(HeuristicFilter::new(config.clone()))
+    }
+
+    pub fn register<F: SpamFilter + 'static>(mut self, filter: F) -> SpamFilters {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn score(&self, conn: &PgConnection, candidate: &Candidate) -> QueryResult<f64> {
+        let mut score = 0.0f64;
+
+        for filter in &self.filters {
+            score = score.max(filter.score(conn, candidate)?);
+        }
+
+        Ok(score)
+    }
+
+    /// The status `candidate` is forced into, if its score calls for one.
+    /// Comments scoring below the review threshold get no verdict and go
+    /// through the usual auto-approval rules.
+    pub fn verdict(&self,
+                   conn: &PgConnection,
+                   candidate: &Candidate)
+                   -> QueryResult<Option<ModerationStatus>> {
+        let score = self.score(conn, candidate)?;
+
+        if score >= self.spam_threshold {
+            Ok(Some(ModerationStatus::Spam))
+        } else if score >= self.review_threshold {
+            Ok(Some(ModerationStatus::Pending))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Scores comments on link count, banned words, repeated content and how
+/// fast their IP is commenting.
+pub struct HeuristicFilter {
+    config: SpamConfig,
+}
+
+impl HeuristicFilter {
+    pub fn new(config: SpamConfig) -> HeuristicFilter {
+        HeuristicFilter { config: config }
+    }
+
+    fn links_score(&self, body: &str) -> f64 {
+        let links = LINK_MARKERS.iter()
+            .map(|marker| body.matches(*marker).count() as u32)
+            .sum::<u32>();
+
+        if links > self.config.max_links {
+            EXCESS_LINK_SCORE * (links - self.config.max_links) as f64
+        } else {
+            0.0
+        }
+    }
+
+    fn banned_words_score(&self, body: &str) -> f64 {
+        let hits = self.config
+            .banned_words
+            .iter()
+            .filter(|word| contains_word(body, word))
+            .count();
+
+        BANNED_WORD_SCORE * hits as f64
+    }
+
+    fn repeated_content_score(&self,
+                              conn: &PgConnection,
+                              candidate: &Candidate)
+                              -> QueryResult<f64> {
+        let since = timestamps::now() - Duration::seconds(self.config.repeat_window as i64);
+
+        let same_author = comments::user_id.eq(candidate.user_id);
+        let repeats = match candidate.ip_address {
+            Some(ip_address) => {
+                comments::table.filter(comments::body.eq(candidate.body))
+                    .filter(comments::created_at.gt(since))
+                    .filter(same_author.or(comments::ip_address.eq(ip_address)))
+                    .count()
+                    .get_result::<i64>(conn)?
+            }
+            None => {
+                comments::table.filter(comments::body.eq(candidate.body))
+                    .filter(comments::created_at.gt(since))
+                    .filter(same_author)
+                    .count()
+                    .get_result::<i64>(conn)?
+            }
+        };
+
+        Ok(if repeats > 0 { REPEATED_CONTENT_SCORE } else { 0.0 })
+    }
+
+    fn velocity_score(&self, conn: &PgConnection, candidate: &Candidate) -> QueryResult<f64> {
+        let ip_address = match candidate.ip_address {
+            Some(ip_address) => ip_address,
+            None => return Ok(0.0),
+        };
+        let since = timestamps::now() - Duration::seconds(self.config.velocity_window as i64);
+
+        let recent = comments::table.filter(comments::ip_address.eq(ip_address))
+            .filter(comments::created_at.gt(since))
+            .count()
+            .get_result::<i64>(conn)?;
+
+        if recent >= self.config.max_comments_per_window as i64 {
+            Ok(VELOCITY_SCORE)
+        } else {
+            Ok(0.0)
+        }
+    }
+}
+
+/// Whether `word` occurs in `text` as a whole word (or phrase), i.e. not
+/// glued to letters or digits on either side: "ass" is not in "class".
+fn contains_word(text: &str, word: &str) -> bool {
+    if word.is_empty() {
+        return false;
+    }
+
+    text.match_indices(word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+
+        !before.map_or(false, char::is_alphanumeric) && !after.map_or(false, char::is_alphanumeric)
+    })
+}
+
+impl SpamFilter for HeuristicFilter {
+    fn score(&self, conn: &PgConnection, candidate: &Candidate) -> QueryResult<f64> {
+        let body = candidate.body.to_lowercase();
+
+        let score = self.links_score(&body) + self.banned_words_score(&body) +
+                    self.repeated_content_score(conn, candidate)? +
+                    self.velocity_score(conn, candidate)?;
+
+        Ok(score.min(1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banned_words_match_whole_words_only() {
+        assert!(contains_word("you ass", "ass"));
+        assert!(contains_word("ass, really", "ass"));
+        assert!(contains_word("buy cheap pills now", "cheap pills"));
+        assert!(!contains_word("first class", "ass"));
+        assert!(!contains_word("assume", "ass"));
+        assert!(!contains_word("anything", ""));
+    }
+}
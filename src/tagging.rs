@@ -0,0 +1,67 @@
+use diesel;
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+
+use models::{NewPostTag, NewTag, Tag};
+use schema::post_tags;
+use schema::tags;
+
+/// Tags are matched case-insensitively, so they are stored trimmed and
+/// lowercased.
+pub fn normalize(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Normalized, de-duplicated tag names, in the order first given.
+pub fn normalize_all(names: &[String]) -> Vec<String> {
+    let mut normalized = Vec::new();
+
+    for name in names.iter().map(|name| normalize(name)) {
+        if !normalized.contains(&name) {
+            normalized.push(name);
+        }
+    }
+
+    normalized
+}
+
+pub fn find_by_name(conn: &PgConnection, name: &str) -> QueryResult<Tag> {
+    tags::table.filter(tags::name.eq(normalize(name))).first::<Tag>(conn)
+}
+
+fn find_or_create(conn: &PgConnection, name: &str) -> QueryResult<Tag> {
+    match tags::table.filter(tags::name.eq(name)).first::<Tag>(conn).optional()? {
+        Some(tag) => Ok(tag),
+        None => {
+            diesel::insert(&NewTag { name: name.to_owned() })
+                .into(tags::table)
+                .get_result::<Tag>(conn)
+        }
+    }
+}
+
+/// Replaces the tags of a post, creating the ones that do not exist yet.
+/// Meant to run inside the transaction saving the post.
+pub fn set_post_tags(conn: &PgConnection, post_id: i32, names: &[String]) -> QueryResult<()> {
+    diesel::delete(post_tags::table.filter(post_tags::post_id.eq(post_id))).execute(conn)?;
+
+    for name in normalize_all(names) {
+        let tag = find_or_create(conn, &name)?;
+
+        diesel::insert(&NewPostTag {
+                post_id: post_id,
+                tag_id: tag.id,
+            })
+            .into(post_tags::table)
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+pub fn tags_of_post(conn: &PgConnection, post_id: i32) -> QueryResult<Vec<Tag>> {
+    tags::table.filter(tags::id.eq_any(post_tags::table.select(post_tags::tag_id)
+            .filter(post_tags::post_id.eq(post_id))))
+        .order(tags::name.asc())
+        .load::<Tag>(conn)
+}
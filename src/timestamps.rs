@@ -0,0 +1,58 @@
+//! Timestamps are stored as UTC `TIMESTAMP`s and travel as RFC 3339 strings.
+//!
+//! Use with `#[serde(with = "timestamps")]`, or `timestamps::option` for
+//! nullable columns.
+
+use chrono::{DateTime, NaiveDateTime, UTC};
+use serde::{Deserialize, Deserializer, Serializer};
+use serde::de::Error;
+
+/// The current time, as stored in the database.
+pub fn now() -> NaiveDateTime {
+    UTC::now().naive_utc()
+}
+
+pub fn to_rfc3339(value: &NaiveDateTime) -> String {
+    DateTime::<UTC>::from_utc(*value, UTC).to_rfc3339()
+}
+
+pub fn from_rfc3339(value: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(value).map(|datetime| datetime.naive_utc()).ok()
+}
+
+pub fn serialize<S: Serializer>(value: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&to_rfc3339(value))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDateTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+
+    from_rfc3339(&value).ok_or_else(|| D::Error::custom("expected an RFC 3339 timestamp"))
+}
+
+pub mod option {
+    use chrono::NaiveDateTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(value: &Option<NaiveDateTime>,
+                                    serializer: S)
+                                    -> Result<S::Ok, S::Error> {
+        match *value {
+            Some(ref value) => serializer.serialize_some(&super::to_rfc3339(value)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D)
+                                                  -> Result<Option<NaiveDateTime>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(value) => {
+                super::from_rfc3339(&value)
+                    .map(Some)
+                    .ok_or_else(|| D::Error::custom("expected an RFC 3339 timestamp"))
+            }
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::error;
+
+use chrono::NaiveDateTime;
+
+use models::{NewComment, NewPost, NewTag, NewUser, PasswordChange};
+use models::{UpdatedComment, UpdatedPost, UpdatedUser};
+use timestamps;
+
+const MAX_TITLE_LENGTH: usize = 255;
+const MAX_POST_BODY_LENGTH: usize = 100_000;
+const MAX_COMMENT_BODY_LENGTH: usize = 10_000;
+const MAX_NAME_LENGTH: usize = 255;
+const MIN_USERNAME_LENGTH: usize = 3;
+const MAX_USERNAME_LENGTH: usize = 32;
+const MAX_EMAIL_LENGTH: usize = 254;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 1024;
+const MAX_TAG_LENGTH: usize = 50;
+const MAX_TAGS_PER_POST: usize = 10;
+
+/// Field-level validation failures, keyed by field name.
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationErrors(BTreeMap<String, Vec<String>>);
+
+impl ValidationErrors {
+    pub fn new() -> ValidationErrors {
+        ValidationErrors::default()
+    }
+
+    pub fn add(&mut self, field: &str, message: &str) {
+        self.0.entry(field.to_owned()).or_insert_with(Vec::new).push(message.to_owned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields = self.0.keys().cloned().collect::<Vec<_>>().join(", ");
+        write!(f, "Invalid fields: {}", fields)
+    }
+}
+
+impl error::Error for ValidationErrors {
+    fn description(&self) -> &str {
+        "validation failed"
+    }
+}
+
+/// Request bodies that must be checked before they reach the database.
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+fn required(errors: &mut ValidationErrors, field: &str, value: &str) {
+    if value.trim().is_empty() {
+        errors.add(field, "must not be blank");
+    }
+}
+
+fn length(errors: &mut ValidationErrors, field: &str, value: &str, min: usize, max: usize) {
+    let len = value.chars().count();
+
+    if len < min {
+        errors.add(field, &format!("must be at least {} characters long", min));
+    } else if len > max {
+        errors.add(field, &format!("must be at most {} characters long", max));
+    }
+}
+
+fn email(errors: &mut ValidationErrors, field: &str, value: &str) {
+    let mut parts = value.split('@');
+    let valid = match (parts.next(), parts.next(), parts.next()) {
+        (Some(local), Some(domain), None) => {
+            !local.is_empty() && domain.contains('.') && !domain.starts_with('.') &&
+            !domain.ends_with('.') && !value.chars().any(char::is_whitespace)
+        }
+        _ => false,
+    };
+
+    if !valid {
+        errors.add(field, "is not a valid email address");
+    }
+    length(errors, field, value, 0, MAX_EMAIL_LENGTH);
+}
+
+fn username(errors: &mut ValidationErrors, field: &str, value: &str) {
+    let valid_char = |c: char| match c {
+        'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '-' => true,
+        _ => false,
+    };
+
+    if !value.chars().all(valid_char) {
+        errors.add(field, "may only contain letters, digits, underscores and dashes");
+    }
+    length(errors, field, value, MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH);
+}
+
+fn title(errors: &mut ValidationErrors, value: &str) {
+    required(errors, "title", value);
+    length(errors, "title", value, 0, MAX_TITLE_LENGTH);
+}
+
+fn post_body(errors: &mut ValidationErrors, value: &str) {
+    required(errors, "body", value);
+    length(errors, "body", value, 0, MAX_POST_BODY_LENGTH);
+}
+
+fn publish_at(errors: &mut ValidationErrors, value: &NaiveDateTime) {
+    if *value <= timestamps::now() {
+        errors.add("publish_at", "must be in the future");
+    }
+}
+
+fn comment_body(errors: &mut ValidationErrors, value: &str) {
+    required(errors, "body", value);
+    length(errors, "body", value, 0, MAX_COMMENT_BODY_LENGTH);
+}
+
+fn name(errors: &mut ValidationErrors, value: &str) {
+    required(errors, "name", value);
+    length(errors, "name", value, 0, MAX_NAME_LENGTH);
+}
+
+fn tag_name(errors: &mut ValidationErrors, field: &str, value: &str) {
+    let valid_char = |c: char| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_';
+
+    required(errors, field, value);
+    if !value.trim().chars().all(valid_char) {
+        errors.add(field, "may only contain letters, digits, spaces, underscores and dashes");
+    }
+    length(errors, field, value.trim(), 0, MAX_TAG_LENGTH);
+}
+
+fn tags(errors: &mut ValidationErrors, values: &[String]) {
+    if values.len() > MAX_TAGS_PER_POST {
+        errors.add("tags", &format!("must have at most {} tags", MAX_TAGS_PER_POST));
+    }
+    for value in values {
+        tag_name(errors, "tags", value);
+    }
+}
+
+fn password(errors: &mut ValidationErrors, field: &str, value: &str) {
+    length(errors, field, value, MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH);
+}
+
+impl Validate for NewPost {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        title(&mut errors, &self.title);
+        post_body(&mut errors, &self.body);
+        if let Some(ref value) = self.publish_at {
+            publish_at(&mut errors, value);
+        }
+        if let Some(ref values) = self.tags {
+            tags(&mut errors, values);
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdatedPost {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if let Some(ref value) = self.title {
+            title(&mut errors, value);
+        }
+        if let Some(ref value) = self.body {
+            post_body(&mut errors, value);
+        }
+        if let Some(ref value) = self.publish_at {
+            publish_at(&mut errors, value);
+        }
+        if let Some(ref values) = self.tags {
+            tags(&mut errors, values);
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for NewTag {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        tag_name(&mut errors, "name", &self.name);
+        errors.into_result()
+    }
+}
+
+impl Validate for NewUser {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        name(&mut errors, &self.name);
+        username(&mut errors, "username", &self.username);
+        email(&mut errors, "email", &self.email);
+        password(&mut errors, "password", &self.password);
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdatedUser {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if let Some(ref value) = self.name {
+            name(&mut errors, value);
+        }
+        if let Some(ref value) = self.username {
+            username(&mut errors, "username", value);
+        }
+        if let Some(ref value) = self.email {
+            email(&mut errors, "email", value);
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for PasswordChange {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        password(&mut errors, "new_password", &self.new_password);
+        errors.into_result()
+    }
+}
+
+impl Validate for NewComment {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        comment_body(&mut errors, &self.body);
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdatedComment {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if let Some(ref value) = self.body {
+            comment_body(&mut errors, value);
+        }
+        errors.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+
+    fn new_user(username: &str, email: &str, password: &str) -> NewUser {
+        NewUser {
+            name: String::from("Jane Doe"),
+            username: username.to_owned(),
+            email: email.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    fn new_post(title: &str, body: &str) -> NewPost {
+        NewPost {
+            title: title.to_owned(),
+            body: body.to_owned(),
+            user_id: None,
+            publish_at: None,
+            tags: None,
+            body_html: String::new(),
+        }
+    }
+
+    fn invalid_fields(result: Result<(), ValidationErrors>) -> Vec<String> {
+        result.err().map(|errors| errors.0.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    #[test]
+    fn accepts_a_valid_user() {
+        assert!(new_user("jane_doe", "jane@example.com", "long enough").validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_usernames() {
+        for username in &["jd", "jane doe", "jane.doe", "jane<b>", "[deleted]"] {
+            let user = new_user(username, "jane@example.com", "long enough");
+            assert_eq!(invalid_fields(user.validate()), vec!["username"]);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_emails() {
+        for email in &["jane", "jane@", "@example.com", "jane@example", "jane@.com",
+                       "jane@example.", "jane doe@example.com", "jane@doe@example.com"] {
+            let user = new_user("jane_doe", email, "long enough");
+            assert_eq!(invalid_fields(user.validate()), vec!["email"]);
+        }
+    }
+
+    #[test]
+    fn rejects_short_passwords() {
+        let user = new_user("jane_doe", "jane@example.com", "short");
+
+        assert_eq!(invalid_fields(user.validate()), vec!["password"]);
+    }
+
+    #[test]
+    fn rejects_long_passwords() {
+        let too_long = "x".repeat(MAX_PASSWORD_LENGTH + 1);
+        let user = new_user("jane_doe", "jane@example.com", &too_long);
+
+        assert_eq!(invalid_fields(user.validate()), vec!["password"]);
+    }
+
+    #[test]
+    fn requires_a_title_and_a_body() {
+        assert!(new_post("Hello", "World").validate().is_ok());
+        assert_eq!(invalid_fields(new_post("  ", "").validate()), vec!["body", "title"]);
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        let title = "é".repeat(MAX_TITLE_LENGTH);
+
+        assert!(new_post(&title, "World").validate().is_ok());
+        assert_eq!(invalid_fields(new_post(&format!("{}é", title), "World").validate()),
+                   vec!["title"]);
+    }
+
+    #[test]
+    fn only_schedules_posts_in_the_future() {
+        let mut post = new_post("Hello", "World");
+
+        post.publish_at = Some(timestamps::now() + Duration::hours(1));
+        assert!(post.validate().is_ok());
+
+        post.publish_at = Some(timestamps::now() - Duration::hours(1));
+        assert_eq!(invalid_fields(post.validate()), vec!["publish_at"]);
+    }
+
+    #[test]
+    fn limits_tags() {
+        let mut post = new_post("Hello", "World");
+
+        post.tags = Some(vec![String::from("rust"), String::from(" web dev ")]);
+        assert!(post.validate().is_ok());
+
+        post.tags = Some(vec![String::from("c++")]);
+        assert_eq!(invalid_fields(post.validate()), vec!["tags"]);
+
+        post.tags = Some((0..MAX_TAGS_PER_POST + 1).map(|i| format!("tag{}", i)).collect());
+        assert_eq!(invalid_fields(post.validate()), vec!["tags"]);
+    }
+
+    #[test]
+    fn only_checks_the_fields_being_updated() {
+        let update = UpdatedUser {
+            name: None,
+            username: None,
+            email: Some(String::from("not an email")),
+        };
+
+        assert_eq!(invalid_fields(update.validate()), vec!["email"]);
+    }
+}